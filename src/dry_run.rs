@@ -0,0 +1,155 @@
+use crate::{
+    execution::{Selection, WatchOverride, plan, prepare_tasks, with_changed_files},
+    format::{CommandSpec, ReadyProbe, Task},
+    logger::Logger,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    process::exit,
+};
+
+const MASK: &str = "********";
+
+// env vars whose name contains one of these are shown masked
+const SECRET_MARKERS: [&str; 8] =
+    ["SECRET", "TOKEN", "PASSWORD", "PASSWD", "KEY", "CREDENTIAL", "AUTH", "PRIVATE"];
+
+#[derive(Serialize)]
+struct Plan {
+    targets: Vec<String>,
+    parallel: bool,
+    tasks: Vec<PlannedTask>,
+}
+
+#[derive(Serialize)]
+struct PlannedTask {
+    name: String,
+    run: bool,
+    skipped_by: Option<&'static str>,
+    deps: Vec<String>,
+    commands: Vec<String>,
+    concurrent: bool,
+    cwd: String,
+    env: BTreeMap<String, String>,
+    watch: Vec<String>,
+    service: bool,
+    ready: Option<ReadyProbe>,
+}
+
+/// Resolves everything a run would do, the same way `run_from_task` does, and prints it instead of
+/// running it.
+pub fn print_plan(
+    tasks: &HashMap<String, Task>,
+    targets: &[String],
+    selection: &Selection,
+    watch: Option<&WatchOverride>,
+    parallel: bool,
+    concurrent_global: bool,
+    json: bool,
+) {
+    let tasks = prepare_tasks(tasks, targets, watch).unwrap_or_else(|err| {
+        Logger::error(&err);
+        exit(1);
+    });
+
+    let cwd = env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_default();
+    let planned = plan(&tasks, targets);
+
+    let plan = Plan {
+        targets: targets.to_vec(),
+        parallel,
+        tasks: planned
+            .iter()
+            .map(|name| {
+                let task = &tasks[name];
+                let concurrent = task.concurrent.unwrap_or(concurrent_global);
+
+                // rendered like `run_task` does on a run that is not about a change
+                let task = with_changed_files(task, &[]);
+                let commands = match &task.cmd {
+                    CommandSpec::Single(s) => vec![s.clone()],
+                    CommandSpec::Multiple(list) => list.clone(),
+                };
+                let skipped_by = selection.excluded_by(targets, &planned, name);
+
+                PlannedTask {
+                    name: name.clone(),
+                    run: skipped_by.is_none(),
+                    skipped_by,
+                    deps: task.deps.clone(),
+                    commands: commands.iter().map(|cmd| mask_command(cmd, &task.env)).collect(),
+                    concurrent,
+                    cwd: cwd.clone(),
+                    env: task.env.iter().map(|(k, v)| (k.clone(), mask_value(k, v))).collect(),
+                    watch: task.watch.clone(),
+                    service: task.service,
+                    ready: task.ready.clone(),
+                }
+            })
+            .collect(),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&plan).expect("plan is serializable"));
+        return;
+    }
+
+    let runs = plan.tasks.iter().filter(|t| t.run).count();
+    Logger::plan_header(runs, plan.tasks.len() - runs, parallel);
+    Logger::plan_detail("cwd", &cwd);
+
+    let mut index = 0;
+    for task in &plan.tasks {
+        if let Some(flag) = task.skipped_by {
+            Logger::plan_skipped(&task.name, flag);
+            continue;
+        }
+
+        index += 1;
+        Logger::plan_task(index, &task.name);
+        for cmd in &task.commands {
+            Logger::command(cmd);
+        }
+        if !task.deps.is_empty() {
+            Logger::plan_detail("deps", &task.deps.join(", "));
+        }
+        if !task.env.is_empty() {
+            let env: Vec<String> = task.env.iter().map(|(k, v)| format!("{k}={v:?}")).collect();
+            Logger::plan_detail("env", &env.join(" "));
+        }
+        if task.concurrent && task.commands.len() > 1 {
+            Logger::plan_detail("concurrent", "commands run in parallel");
+        }
+        if task.service {
+            let ready = match &task.ready {
+                Some(ReadyProbe::Tcp(address)) => format!("ready on tcp {address}"),
+                Some(ReadyProbe::Http(url)) => format!("ready on http {url}"),
+                Some(ReadyProbe::Log(pattern)) => format!("ready on log /{pattern}/"),
+                Some(ReadyProbe::File(path)) => format!("ready on file {path}"),
+                None => "ready once started".to_string(),
+            };
+            Logger::plan_detail("service", &ready);
+        }
+        if !task.watch.is_empty() {
+            Logger::plan_detail("watch", &task.watch.join(", "));
+        }
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_uppercase();
+    SECRET_MARKERS.iter().any(|marker| key.contains(marker))
+}
+
+fn mask_value(key: &str, value: &str) -> String {
+    if is_secret(key) && !value.is_empty() { MASK.to_string() } else { value.to_string() }
+}
+
+/// Hides the values of secret env vars that were pasted into a command as-is.
+fn mask_command(cmd: &str, env: &HashMap<String, String>) -> String {
+    env.iter()
+        .filter(|(k, v)| is_secret(k) && !v.is_empty())
+        .fold(cmd.to_string(), |cmd, (_, v)| cmd.replace(v.as_str(), MASK))
+}
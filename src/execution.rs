@@ -1,12 +1,23 @@
 use crate::{
-    format::{CommandSpec, Task},
+    format::{CommandSpec, Config, Task, WatchPolicy},
+    keys,
     logger::Logger,
-    watch::watch_task,
+    process, service, signals,
+    tasks_file::{load_tasks_file, validate_tasks_file},
+    watch::{Change, ContentHashes, WatchEvent, WatchHandle, WatchOptions, WatchService},
 };
+use glob::Pattern;
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    env,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
     process::{Command, exit},
-    sync::Arc,
+    slice,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
     thread,
     time::Duration,
 };
@@ -25,12 +36,13 @@ pub fn topological_order(tasks: &HashMap<String, Task>) -> Vec<String> {
         }
     }
 
-    let mut queue: VecDeque<String> =
+    // tasks that are ready run by name, so the order is the same on every run
+    let mut queue: BTreeSet<String> =
         indegree.iter().filter(|(_, deg)| **deg == 0).map(|(n, _)| n.clone()).collect();
 
     let mut result = Vec::new();
 
-    while let Some(node) = queue.pop_front() {
+    while let Some(node) = queue.pop_first() {
         result.push(node.clone());
 
         if let Some(neighbors) = graph.get(&node) {
@@ -38,7 +50,7 @@ pub fn topological_order(tasks: &HashMap<String, Task>) -> Vec<String> {
                 let deg = indegree.get_mut(neighbor).unwrap();
                 *deg -= 1;
                 if *deg == 0 {
-                    queue.push_back(neighbor.clone());
+                    queue.insert(neighbor.clone());
                 }
             }
         }
@@ -65,105 +77,1031 @@ pub fn collect_dependencies(tasks: &HashMap<String, Task>, start: &str) -> HashS
     visited
 }
 
-fn collect_dependents(tasks: &HashMap<String, Task>, changed: &str) -> HashSet<String> {
-    let mut dependents = HashSet::new();
+/// Restrictions given on the command line on which of the planned tasks actually run.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    /// Run the targets only, without their dependencies.
+    pub no_deps: bool,
+    /// Run only these tasks.
+    pub only: Vec<String>,
+    /// Resume the plan from this task, e.g. after it failed.
+    pub from: Option<String>,
+    /// Leave these tasks out.
+    pub skip: Vec<String>,
+    /// With `--since`, run only the tasks affected by the changes.
+    pub since: Option<HashSet<String>>,
+}
+
+impl Selection {
+    /// The flag that leaves a task of the plan out of the run, if any.
+    pub fn excluded_by(
+        &self,
+        targets: &[String],
+        planned: &[String],
+        name: &str,
+    ) -> Option<&'static str> {
+        let position = |task: &str| planned.iter().position(|t| t == task);
+        if let Some(from) = &self.from
+            && position(name) < position(from)
+        {
+            return Some("--from");
+        }
+
+        if self.no_deps && !targets.iter().any(|t| t == name) {
+            Some("--no-deps")
+        } else if !self.only.is_empty() && !self.only.iter().any(|t| t == name) {
+            Some("--only")
+        } else if self.skip.iter().any(|t| t == name) {
+            Some("--skip")
+        } else if self.since.as_ref().is_some_and(|affected| !affected.contains(name)) {
+            Some("--since")
+        } else {
+            None
+        }
+    }
+
+    pub fn apply(&self, targets: &[String], planned: &[String]) -> Vec<String> {
+        planned
+            .iter()
+            .filter(|name| self.excluded_by(targets, planned, name).is_none())
+            .cloned()
+            .collect()
+    }
+}
+
+/// The tasks the session runs, in order: the plan of the targets, narrowed down by the selection.
+fn session_plan(
+    tasks: &HashMap<String, Task>,
+    targets: &[String],
+    selection: &Selection,
+) -> Vec<String> {
+    selection.apply(targets, &plan(tasks, targets))
+}
+
+/// Every task the targets need, each once: target by target, the dependencies not planned yet in
+/// dependency order, then the target itself.
+pub fn plan(tasks: &HashMap<String, Task>, targets: &[String]) -> Vec<String> {
+    let order = topological_order(tasks);
+    let mut planned = Vec::new();
+    let mut seen = HashSet::new();
+
+    for target in targets {
+        let deps = collect_dependencies(tasks, target);
+        for name in order.iter().filter(|t| deps.contains(*t)) {
+            if seen.insert(name) {
+                planned.push(name.clone());
+            }
+        }
+    }
 
+    planned
+}
+
+/// Every task that depends on `changed`, directly or transitively. Each task is visited once.
+pub fn collect_dependents(tasks: &HashMap<String, Task>, changed: &str) -> HashSet<String> {
+    // dep -> task
+    let mut graph: HashMap<&str, Vec<&str>> = HashMap::new();
     for (name, task) in tasks {
-        if task.deps.contains(&changed.to_string()) {
-            dependents.insert(name.clone());
-            dependents.extend(collect_dependents(tasks, name));
+        for dep in &task.deps {
+            graph.entry(dep).or_default().push(name);
+        }
+    }
+
+    let mut dependents = HashSet::new();
+    let mut queue = VecDeque::from([changed]);
+
+    while let Some(node) = queue.pop_front() {
+        for &dependent in graph.get(node).into_iter().flatten() {
+            if dependents.insert(dependent.to_string()) {
+                queue.push_back(dependent);
+            }
         }
     }
 
     dependents
 }
 
-pub fn run_from_task(tasks: &HashMap<String, Task>, start: &str, concurrent_global: bool) {
-    let deps = collect_dependencies(tasks, start);
-    let order = topological_order(tasks);
-    let filtered: Vec<String> = order.into_iter().filter(|t| deps.contains(t)).collect();
+/// Tasks that have to re-run, in order, before a `watch_deps` task whose dependencies' files
+/// changed: the dependencies owning those files and everything between them and the task.
+fn dependency_chain(
+    tasks: &HashMap<String, Task>,
+    task_name: &str,
+    changed: &[PathBuf],
+) -> Vec<String> {
+    let closure = collect_dependencies(tasks, task_name);
+
+    let mut affected: HashSet<String> = closure
+        .iter()
+        .filter(|name| *name != task_name)
+        .filter(|name| {
+            own_patterns(&tasks[*name])
+                .iter()
+                .filter_map(|pattern| Pattern::new(pattern).ok())
+                .any(|pattern| changed.iter().any(|path| pattern.matches_path(path)))
+        })
+        .cloned()
+        .collect();
+
+    let downstream: Vec<String> =
+        affected.iter().flat_map(|name| collect_dependents(tasks, name)).collect();
+    affected.extend(downstream.into_iter().filter(|name| closure.contains(name)));
+
+    topological_order(tasks)
+        .into_iter()
+        .filter(|name| name != task_name && affected.contains(name))
+        .collect()
+}
+
+/// The files a task itself cares about: its `watch` patterns, or else its `sources`.
+fn own_patterns(task: &Task) -> &[String] {
+    if task.watch.is_empty() { &task.sources } else { &task.watch }
+}
 
-    let mut has_watchers = false;
-    let tasks_arc = Arc::new(tasks.clone());
+/// Watch settings given on the command line for the task being run.
+#[derive(Debug, Clone, Default)]
+pub struct WatchOverride {
+    pub globs: Vec<String>,
+    pub deps: bool,
+}
+
+/// Applies the command line watch settings and `watch_deps` to a freshly loaded set of tasks.
+pub fn prepare_tasks(
+    tasks: &HashMap<String, Task>,
+    targets: &[String],
+    watch: Option<&WatchOverride>,
+) -> Result<HashMap<String, Task>, String> {
+    let original = tasks;
+    let mut tasks = tasks.clone();
 
-    for task_name in filtered {
-        if let Some(task) = tasks.get(&task_name) {
-            Logger::task_start(&task_name);
+    if let Some(watch) = watch {
+        if let Some(pattern) = watch.globs.iter().find(|p| Pattern::new(p).is_err()) {
+            return Err(format!("invalid watch pattern: '{pattern}'"));
+        }
 
-            if !task.watch.is_empty() {
-                has_watchers = true;
+        for target in targets {
+            let Some(task) = tasks.get_mut(target) else {
+                continue;
+            };
 
-                let task_name_clone = task_name.clone();
-                let task_clone = task.clone();
-                let tasks_clone = tasks_arc.clone();
-                let concurrent_global_clone = concurrent_global;
+            if !watch.globs.is_empty() {
+                task.watch = watch.globs.clone();
+            } else if task.watch.is_empty() {
+                task.watch = task.sources.clone();
+            }
+            task.watch_deps |= watch.deps;
+        }
+    }
 
-                let watch = task_clone.watch.clone();
-                let watch_debounce = task_clone.watch_debounce;
-                let watch_propagate = task_clone.watch_propagate;
-                let task_for_run = task_clone.clone();
+    let order = topological_order(original);
+    for (name, task) in tasks.iter_mut().filter(|(_, t)| t.watch_deps) {
+        let deps = collect_dependencies(original, name);
+        for dep in order.iter().filter(|t| *t != name && deps.contains(*t)) {
+            for pattern in own_patterns(&original[dep]) {
+                if !task.watch.contains(pattern) {
+                    task.watch.push(pattern.clone());
+                }
+            }
+        }
+    }
 
+    if watch.is_some()
+        && let Some(target) =
+            targets.iter().find(|t| tasks.get(*t).is_some_and(|t| t.watch.is_empty()))
+    {
+        return Err(format!(
+            "task '{target}' has nothing to watch, give it `sources` or pass patterns to --watch"
+        ));
+    }
+
+    Ok(tasks)
+}
+
+type SharedTasks = Arc<RwLock<HashMap<String, Task>>>;
+
+/// What the watchers of a session share, so a reloaded tasks file can be applied to them.
+struct Session {
+    targets: Vec<String>,
+    tasks_file: PathBuf,
+    config: Config,
+    watch_override: Option<WatchOverride>,
+    selection: Selection,
+    root: PathBuf,
+    tasks: SharedTasks,
+    runners: Mutex<HashMap<String, Arc<WatchRunner>>>,
+    watch: WatchHandle,
+    paused: AtomicBool,
+}
+
+impl Session {
+    fn runner(&self, task_name: &str) -> Arc<WatchRunner> {
+        let mut runners = self.runners.lock().unwrap();
+        let runner = runners.entry(task_name.to_string()).or_insert_with(|| {
+            Arc::new(WatchRunner {
+                task_name: task_name.to_string(),
+                tasks: self.tasks.clone(),
+                concurrent_global: self.config.concurrent,
+                propagate_parallel: self.config.watch_propagate_parallel,
+                stop_timeout: Duration::from_millis(self.config.shutdown_timeout),
+                root: self.root.clone(),
+                watch: self.watch.clone(),
+                state: Mutex::new(RunnerState::default()),
+            })
+        });
+
+        runner.clone()
+    }
+
+    /// The tasks the session runs, in order.
+    fn order(&self) -> Vec<String> {
+        session_plan(&self.tasks.read().unwrap(), &self.targets, &self.selection)
+    }
+
+    /// Runs a task again on the calling thread. Watched tasks go through their runner, so a run
+    /// that is already in progress gets restarted instead of doubled.
+    fn rerun(&self, task_name: &str) -> bool {
+        let Some(task) = self.tasks.read().unwrap().get(task_name).cloned() else {
+            return false;
+        };
+
+        Logger::task_start(&format!("♻️  {task_name}"));
+        if task.watch.is_empty() {
+            return run_task(&task_name.to_string(), &task, &[], self.config.concurrent);
+        }
+
+        self.runner(task_name).run_now();
+        true
+    }
+}
+
+pub fn run_from_task(
+    tasks: &HashMap<String, Task>,
+    targets: &[String],
+    parallel: bool,
+    config: &Config,
+    tasks_file: &Path,
+    watch_override: Option<WatchOverride>,
+    selection: Selection,
+) {
+    let concurrent_global = config.concurrent;
+
+    let tasks = &prepare_tasks(tasks, targets, watch_override.as_ref()).unwrap_or_else(|err| {
+        Logger::error(&err);
+        exit(1);
+    });
+
+    let planned = plan(tasks, targets);
+    let filtered = selection.apply(targets, &planned);
+    let skipped: Vec<String> = planned.into_iter().filter(|t| !filtered.contains(t)).collect();
+    if !skipped.is_empty() {
+        Logger::tasks_skipped(&skipped);
+    }
+    if filtered.is_empty() {
+        Logger::warn("nothing left to run");
+        return;
+    }
+
+    let mut service = WatchService::new(Duration::from_millis(config.watch_poll_interval));
+    for task_name in &filtered {
+        if let Some(task) = tasks.get(task_name).filter(|t| !t.watch.is_empty()) {
+            service.subscribe(task_name, WatchOptions::from_task(task, config));
+        }
+    }
+
+    let root = match tasks_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let session = Arc::new(Session {
+        targets: targets.to_vec(),
+        tasks_file: tasks_file.to_path_buf(),
+        config: config.clone(),
+        watch_override,
+        selection,
+        root: root.canonicalize().unwrap_or(root),
+        tasks: Arc::new(RwLock::new(tasks.clone())),
+        runners: Mutex::default(),
+        watch: service.handle(),
+        paused: AtomicBool::new(false),
+    });
+
+    let has_watchers = !service.is_empty();
+    if has_watchers {
+        service.watch_tasks_file(tasks_file, config.watch_mode);
+
+        let session = session.clone();
+        thread::spawn(move || {
+            service.run(move |event| match event {
+                WatchEvent::Changed(batch) => dispatch_changes(&session, batch),
+                WatchEvent::TasksFileChanged => reload_tasks_file(&session),
+            })
+        });
+    }
+
+    // shortcuts work during the initial runs too, which a dev server never finishes
+    if has_watchers && keys::is_interactive() {
+        Logger::keys_hint();
+        let session = session.clone();
+        thread::spawn(move || listen_for_keys(&session));
+    }
+
+    let run = |task_name: &String| {
+        let task = &tasks[task_name];
+        Logger::task_start(task_name);
+
+        if task.watch.is_empty() {
+            return run_task(task_name, task, &[], concurrent_global);
+        }
+
+        // changes only reach a task once its runner exists, i.e. its first run has started
+        session.runner(task_name).run_initial();
+        true
+    };
+
+    if parallel {
+        run_in_waves(tasks, &filtered, run);
+    } else {
+        for task_name in &filtered {
+            run(task_name);
+        }
+    }
+
+    if !has_watchers {
+        // services run on their own keep the session alive; otherwise services only live as
+        // long as the tasks that need them
+        let services: Vec<&String> =
+            targets.iter().filter(|t| tasks[*t].service && service::is_up(t)).collect();
+        for target in &services {
+            Logger::service_waiting(target);
+        }
+        while services.iter().any(|t| service::is_up(t)) {
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        service::stop_all();
+        return;
+    }
+
+    Logger::waiting();
+    loop {
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Handles keyboard shortcuts until stdin closes or the user quits.
+fn listen_for_keys(session: &Arc<Session>) {
+    let _raw_input = keys::RawInput::enable();
+
+    while let Some(key) = keys::read_key() {
+        match key {
+            'r' => {
+                let session = session.clone();
                 thread::spawn(move || {
-                    watch_task(&watch, watch_debounce, move || {
-                        Logger::task_start(&format!("♻️  {}", &task_name_clone));
-                        run_task(&task_name_clone, &task_for_run, concurrent_global_clone);
-
-                        if watch_propagate {
-                            let dependents = collect_dependents(&tasks_clone, &task_name_clone);
-                            for dep_name in dependents {
-                                if let Some(dep_task) = tasks_clone.get(&dep_name) {
-                                    Logger::dependency_propagated(&dep_name);
-                                    run_task(&dep_name, dep_task, concurrent_global_clone);
-                                }
-                            }
+                    for task_name in session.order() {
+                        if !session.rerun(&task_name) {
+                            break;
                         }
-                    });
+                    }
                 });
             }
+            'R' => {
+                let order = session.order();
+                Logger::pick_task(&order);
+                let Some(choice) = keys::read_line() else {
+                    continue;
+                };
 
-            run_task(&task_name, task, concurrent_global);
+                let picked = match choice.parse::<usize>() {
+                    Ok(n) => n.checked_sub(1).and_then(|i| order.get(i)),
+                    Err(_) => order.iter().find(|name| **name == choice),
+                };
+                match picked.cloned() {
+                    Some(task_name) => {
+                        let session = session.clone();
+                        thread::spawn(move || session.rerun(&task_name));
+                    }
+                    None if choice.is_empty() => {}
+                    None => Logger::warn(&format!("no such task: {choice}")),
+                }
+            }
+            'c' => Logger::clear_screen(),
+            'p' => {
+                let paused = !session.paused.fetch_xor(true, Ordering::SeqCst);
+                session.watch.set_paused(paused);
+                Logger::watch_paused(paused);
+            }
+            'q' => {
+                signals::quit();
+                return;
+            }
+            'h' => Logger::keys_help(),
+            _ => {}
         }
     }
+}
 
-    if has_watchers {
-        Logger::waiting();
+/// Triggers the watched tasks whose files changed, in dependency order. The dependents of the
+/// batch's `watch_propagate` tasks run once, after all of them; a changed task among those waits
+/// for its upstream runs, then runs through its own runner with its own files.
+fn dispatch_changes(session: &Arc<Session>, mut batch: Vec<(String, Change)>) {
+    let tasks = session.tasks.read().unwrap().clone();
+
+    let order = topological_order(&tasks);
+    batch.sort_by_key(|(name, _)| order.iter().position(|t| t == name));
+
+    // `watch_deps` tasks re-run the dependencies whose files changed themselves
+    let chained: HashSet<String> = batch
+        .iter()
+        .filter(|(name, _)| tasks.get(name).is_some_and(|t| t.watch_deps))
+        .flat_map(|(name, _)| {
+            collect_dependencies(&tasks, name).into_iter().filter(move |t| t != name)
+        })
+        .collect();
+    batch.retain(|(name, _)| !chained.contains(name));
+
+    // everything the batch propagates to, so each dependent runs once for the whole batch
+    let dependents: HashSet<String> = batch
+        .iter()
+        .filter(|(name, _)| tasks.get(name).is_some_and(|t| t.watch_propagate))
+        .flat_map(|(name, _)| collect_dependents(&tasks, name))
+        .collect();
+
+    let runners = session.runners.lock().unwrap().clone();
+    let (deferred, direct): (Vec<_>, Vec<_>) =
+        batch.into_iter().partition(|(name, _)| dependents.contains(name));
+    let upstream: HashSet<String> = direct
+        .iter()
+        .map(|(name, _)| name.clone())
+        .filter(|name| runners.contains_key(name) && tasks[name].watch_propagate)
+        .collect();
+
+    let propagation = (!upstream.is_empty()).then(|| {
+        Arc::new(Batch {
+            session: session.clone(),
+            order: order.into_iter().filter(|t| dependents.contains(t)).collect(),
+            deferred: deferred.into_iter().collect(),
+            tasks,
+            state: Mutex::new(BatchState { upstream: upstream.len(), completed: false }),
+        })
+    });
+
+    for (name, change) in direct {
+        if let Some(runner) = runners.get(&name) {
+            let batch = propagation.clone().filter(|_| upstream.contains(&name));
+            runner.trigger(change, batch);
+        }
+    }
+}
+
+struct BatchState {
+    // propagating runs of the batch that haven't finished yet
+    upstream: usize,
+    completed: bool,
+}
+
+/// The dependents of a batch of changes. They run once, in order, after every propagating task
+/// of the batch is done with its run; dependents whose own files changed run through their runner
+/// with those files.
+struct Batch {
+    session: Arc<Session>,
+    tasks: HashMap<String, Task>,
+    order: Vec<String>,
+    deferred: HashMap<String, Change>,
+    state: Mutex<BatchState>,
+}
+
+impl Batch {
+    /// Called by each propagating task of the batch once its run completed, or was dropped or
+    /// cancelled.
+    fn done(self: &Arc<Self>, completed: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.upstream -= 1;
+        state.completed |= completed;
+        if state.upstream > 0 {
+            return;
+        }
+
+        let completed = state.completed;
+        drop(state);
+
+        let batch = self.clone();
+        thread::spawn(move || batch.propagate(completed));
+    }
+
+    fn propagate(&self, upstream_completed: bool) {
+        let runners = self.session.runners.lock().unwrap().clone();
+
+        // nothing ran upstream, so only the tasks whose own files changed are due
+        if !upstream_completed {
+            for (name, change) in &self.deferred {
+                if let Some(runner) = runners.get(name) {
+                    runner.trigger(change.clone(), None);
+                }
+            }
+            return;
+        }
+
+        let run = |name: &String| {
+            if let Some(change) = self.deferred.get(name)
+                && let Some(runner) = runners.get(name)
+            {
+                return runner.run_changed(change.clone());
+            }
+
+            Logger::dependency_propagated(name);
+            run_task(name, &self.tasks[name], &[], self.session.config.concurrent)
+        };
+
+        if self.session.config.watch_propagate_parallel {
+            run_in_waves(&self.tasks, &self.order, run);
+        } else {
+            for name in &self.order {
+                if !run(name) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Re-parses and re-validates the tasks file, then restarts only the watched tasks whose
+/// definition changed. An invalid file leaves the session running on the previous tasks.
+fn reload_tasks_file(session: &Session) {
+    Logger::tasks_file_changed();
+
+    let file = match load_tasks_file(&session.tasks_file) {
+        Ok(file) => file,
+        Err(err) => {
+            Logger::error(&err);
+            Logger::reload_rejected();
+            return;
+        }
+    };
+
+    let errors = validate_tasks_file(file.clone());
+    if !errors.is_empty() {
+        Logger::error("validation errors found:");
+        for error in errors {
+            Logger::validation_error(&error.to_string());
+        }
+        Logger::reload_rejected();
+        return;
+    }
+
+    if file.config != session.config {
+        Logger::warn("changes to [config] take effect after restarting tazk");
+    }
+
+    let new = match prepare_tasks(&file.tasks, &session.targets, session.watch_override.as_ref()) {
+        Ok(tasks) => tasks,
+        Err(err) => {
+            Logger::error(&err);
+            Logger::reload_rejected();
+            return;
+        }
+    };
+    let old = std::mem::replace(&mut *session.tasks.write().unwrap(), new.clone());
+    let in_session: HashSet<String> =
+        session_plan(&new, &session.targets, &session.selection).into_iter().collect();
+    let stop_timeout = Duration::from_millis(session.config.shutdown_timeout);
+
+    // tasks that were removed, or are no longer needed by the session
+    let stale: Vec<String> = session
+        .runners
+        .lock()
+        .unwrap()
+        .keys()
+        .filter(|name| !in_session.contains(*name))
+        .cloned()
+        .collect();
+    for name in stale {
+        Logger::task_unloaded(&name);
+        session.watch.unsubscribe(&name);
+        session.runners.lock().unwrap().remove(&name);
+        process::stop_task(&name, stop_timeout);
+        // the task may still run later, e.g. through propagation or as a dependency
+        process::resume_task(&name);
+    }
+
+    for name in topological_order(&new).into_iter().filter(|name| in_session.contains(name)) {
+        let task = &new[&name];
+        let previous = old.get(&name);
+        if previous == Some(task) {
+            continue;
+        }
+
+        let watched = !task.watch.is_empty();
+        let was_watched = previous.is_some_and(|t| !t.watch.is_empty());
+        if !watched && !was_watched {
+            // a running service is replaced by one with the new definition
+            if task.service && service::is_up(&name) {
+                Logger::task_reloaded(&name);
+                let task = task.clone();
+                thread::spawn(move || service::start(&name, &task));
+            }
+            continue;
+        }
+
+        session.watch.unsubscribe(&name);
+        if !watched {
+            Logger::task_unloaded(&name);
+            session.runners.lock().unwrap().remove(&name);
+            process::stop_task(&name, stop_timeout);
+            process::resume_task(&name);
+            continue;
+        }
+
+        Logger::task_reloaded(&name);
+        session.watch.subscribe(&name, WatchOptions::from_task(task, &session.config));
+        session.runner(&name).reload();
+    }
+
+    Logger::reload_ok();
+}
+
+#[derive(Default)]
+struct RunnerState {
+    running: bool,
+    pending: bool,
+    changed: Vec<String>,
+    hashes: ContentHashes,
+}
+
+/// Owns the runs of a watched task, so that changes arriving while it is busy are handled by the
+/// task's `watch_policy` instead of piling up in the watcher.
+struct WatchRunner {
+    task_name: String,
+    tasks: SharedTasks,
+    concurrent_global: bool,
+    propagate_parallel: bool,
+    stop_timeout: Duration,
+    root: PathBuf,
+    watch: WatchHandle,
+    state: Mutex<RunnerState>,
+}
+
+impl WatchRunner {
+    fn run_initial(&self) {
+        self.state.lock().unwrap().running = true;
+        self.run_once();
+        self.drain();
+    }
+
+    /// Runs the task right away, or restarts it when it is already running.
+    fn run_now(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.running {
+            state.pending = true;
+            process::stop_task(&self.task_name, self.stop_timeout);
+            return;
+        }
+
+        state.running = true;
+        drop(state);
+
+        self.run_once();
+        self.drain();
+    }
+
+    fn run_once(&self) {
+        let tasks = self.tasks.read().unwrap().clone();
+        if let Some(task) = tasks.get(&self.task_name) {
+            run_task(&self.task_name, task, &[], self.concurrent_global);
+        }
+    }
+
+    /// Runs the task for a change in the background. When `batch` is given, the run reports to it
+    /// instead of propagating on its own.
+    fn trigger(self: &Arc<Self>, change: Change, batch: Option<Arc<Batch>>) {
+        let mut state = self.state.lock().unwrap();
+
+        let changed: Vec<String> =
+            change.paths.iter().map(|path| self.relative_to_root(path)).collect();
+
+        if !state.running {
+            state.running = true;
+
+            let runner = self.clone();
+            thread::spawn(move || {
+                match batch {
+                    Some(batch) => batch.done(runner.rerun(&changed, change.hashes, false)),
+                    None => {
+                        runner.rerun(&changed, change.hashes, true);
+                    }
+                }
+                runner.drain();
+            });
+            return;
+        }
+
+        self.queue_change(&mut state, changed, change.hashes);
+        drop(state);
+
+        // the queued run propagates on its own later, if it runs at all
+        if let Some(batch) = batch {
+            batch.done(false);
+        }
+    }
+
+    /// Runs the task for a change on the calling thread, without propagating. Returns false when
+    /// the run was cancelled.
+    fn run_changed(&self, change: Change) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        let changed: Vec<String> =
+            change.paths.iter().map(|path| self.relative_to_root(path)).collect();
+
+        if state.running {
+            self.queue_change(&mut state, changed, change.hashes);
+            return true;
+        }
+
+        state.running = true;
+        drop(state);
+
+        let completed = self.rerun(&changed, change.hashes, false);
+        self.drain();
+        completed
+    }
+
+    /// Handles a change that arrives while the task is running, by its `watch_policy`.
+    fn queue_change(&self, state: &mut RunnerState, changed: Vec<String>, hashes: ContentHashes) {
+        let tasks = self.tasks.read().unwrap();
+        let policy = tasks.get(&self.task_name).map(|t| t.watch_policy).unwrap_or_default();
+        drop(tasks);
+
+        if policy != WatchPolicy::Ignore {
+            for file in changed {
+                if !state.changed.contains(&file) {
+                    state.changed.push(file);
+                }
+            }
+            state.hashes.extend(hashes);
+        }
+
+        match policy {
+            WatchPolicy::Queue => state.pending = true,
+            WatchPolicy::Restart => {
+                state.pending = true;
+                // this may be the watcher's thread, so the current run is only asked to stop;
+                // `drain` starts the next one once it has returned
+                process::cancel_task(&self.task_name, self.stop_timeout);
+            }
+            WatchPolicy::Ignore => Logger::change_ignored(&self.task_name),
+        }
+    }
+
+    /// Cancels the current run, if any, and runs the task again with its new definition.
+    fn reload(self: &Arc<Self>) {
+        let runner = self.clone();
+        thread::spawn(move || runner.run_now());
+    }
+
+    fn drain(&self) {
         loop {
-            thread::sleep(Duration::from_secs(1));
+            let mut state = self.state.lock().unwrap();
+            if !state.pending {
+                state.running = false;
+                return;
+            }
+            state.pending = false;
+            let changed = std::mem::take(&mut state.changed);
+            let hashes = std::mem::take(&mut state.hashes);
+            drop(state);
+
+            self.rerun(&changed, hashes, true);
+        }
+    }
+
+    /// Runs the task again for the changed files, after the dependencies whose files changed with
+    /// `watch_deps`. Returns false when a run was cancelled; otherwise the content the run saw
+    /// becomes the `watch_hash` baseline.
+    fn rerun(&self, changed: &[String], hashes: ContentHashes, propagate: bool) -> bool {
+        process::resume_task(&self.task_name);
+        let tasks = self.tasks.read().unwrap().clone();
+
+        // with `watch_deps`, the dependencies whose files changed run first
+        if tasks.get(&self.task_name).is_some_and(|t| t.watch_deps) {
+            let paths: Vec<PathBuf> =
+                changed.iter().map(|file| self.relative_to_cwd(file)).collect();
+            for dep in dependency_chain(&tasks, &self.task_name, &paths) {
+                Logger::task_start(&format!("♻️  {dep}"));
+                if !run_task(&dep, &tasks[&dep], &[], self.concurrent_global) {
+                    return false;
+                }
+            }
+        }
+
+        let completed = rerun_watched(
+            &self.task_name,
+            &tasks,
+            changed,
+            self.concurrent_global,
+            self.propagate_parallel,
+            propagate,
+        );
+        if completed {
+            self.watch.commit(&self.task_name, hashes);
         }
+        completed
+    }
+
+    /// Maps a path relative to the tasks file back to one the watch patterns can match.
+    fn relative_to_cwd(&self, file: &str) -> PathBuf {
+        let absolute = self.root.join(file);
+        env::current_dir()
+            .ok()
+            .and_then(|cwd| absolute.strip_prefix(cwd).ok().map(Path::to_path_buf))
+            .unwrap_or(absolute)
+    }
+
+    /// Changed paths are reported relative to the tasks file rather than the working directory.
+    fn relative_to_root(&self, path: &Path) -> String {
+        let absolute = env::current_dir().map(|cwd| cwd.join(path)).unwrap_or(path.to_path_buf());
+        absolute.strip_prefix(&self.root).unwrap_or(&absolute).display().to_string()
+    }
+}
+
+fn rerun_watched(
+    task_name: &String,
+    tasks: &HashMap<String, Task>,
+    changed: &[String],
+    concurrent_global: bool,
+    propagate_parallel: bool,
+    propagate_changes: bool,
+) -> bool {
+    let Some(task) = tasks.get(task_name) else {
+        return false;
+    };
+
+    Logger::task_start(&format!("♻️  {task_name}"));
+
+    let completed = if task.watch_per_file && !changed.is_empty() {
+        changed
+            .iter()
+            .all(|file| run_task(task_name, task, slice::from_ref(file), concurrent_global))
+    } else {
+        run_task(task_name, task, changed, concurrent_global)
+    };
+
+    if completed && propagate_changes && task.watch_propagate {
+        propagate(task_name, tasks, concurrent_global, propagate_parallel);
     }
+    completed
 }
 
-fn run_task(task_name: &String, task: &Task, concurrent_global: bool) {
+/// Runs every dependent of `task_name` once, after the dependencies it relies on. With
+/// `parallel`, dependents that don't depend on each other run at the same time.
+fn propagate(
+    task_name: &str,
+    tasks: &HashMap<String, Task>,
+    concurrent_global: bool,
+    parallel: bool,
+) {
+    let dependents = collect_dependents(tasks, task_name);
+    let order: Vec<String> =
+        topological_order(tasks).into_iter().filter(|t| dependents.contains(t)).collect();
+
+    let run_dependent = |name: &String| {
+        Logger::dependency_propagated(name);
+        run_task(name, &tasks[name], &[], concurrent_global)
+    };
+
+    if !parallel {
+        for name in &order {
+            if !run_dependent(name) {
+                return;
+            }
+        }
+        return;
+    }
+
+    run_in_waves(tasks, &order, run_dependent);
+}
+
+/// Runs `order` (already in dependency order) level by level: a task's level is one past the
+/// deepest dependency it has within `order`, so the tasks of a level run in parallel once the
+/// levels before them are done. Stops early when a run is cancelled.
+fn run_in_waves<F>(tasks: &HashMap<String, Task>, order: &[String], run: F) -> bool
+where
+    F: Fn(&String) -> bool + Sync,
+{
+    let mut levels: HashMap<&str, usize> = HashMap::new();
+    let mut waves: Vec<Vec<&String>> = Vec::new();
+    for name in order {
+        let level = tasks[name]
+            .deps
+            .iter()
+            .filter_map(|dep| levels.get(dep.as_str()))
+            .map(|level| level + 1)
+            .max()
+            .unwrap_or(0);
+
+        levels.insert(name, level);
+        if waves.len() <= level {
+            waves.resize_with(level + 1, Vec::new);
+        }
+        waves[level].push(name);
+    }
+
+    waves.into_iter().all(|wave| {
+        let results: Vec<bool> = thread::scope(|scope| {
+            let handles: Vec<_> = wave.iter().map(|name| scope.spawn(|| run(name))).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        results.into_iter().all(|ok| ok)
+    })
+}
+
+/// Exposes the changed files to the commands of a task, through the `{{ changed_files }}` template
+/// and the `TAZK_CHANGED_FILES` env var (one path per line), unless the task sets that one itself.
+pub fn with_changed_files(task: &Task, files: &[String]) -> Task {
+    let mut task = task.clone();
+
+    let quoted: Vec<String> = files.iter().map(|file| shell_quote(file)).collect();
+    let quoted = quoted.join(" ");
+    let render = |cmd: &String| {
+        cmd.replace("{{ changed_files }}", &quoted).replace("{{changed_files}}", &quoted)
+    };
+
+    task.cmd = match &task.cmd {
+        CommandSpec::Single(s) => CommandSpec::Single(render(s)),
+        CommandSpec::Multiple(list) => CommandSpec::Multiple(list.iter().map(render).collect()),
+    };
+    task.env.entry("TAZK_CHANGED_FILES".to_string()).or_insert_with(|| files.join("\n"));
+
+    task
+}
+
+fn shell_quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "/._-+,:@".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        return value.to_string();
+    }
+
+    #[cfg(unix)]
+    return format!("'{}'", value.replace('\'', "'\\''"));
+
+    // cmd expands `%` even between double quotes, and a `"` would close them: both are escaped
+    // with `^` outside the quotes
+    #[cfg(windows)]
+    return format!("\"{}\"", value.replace('"', "\"\\^\"\"").replace('%', "\"^%\""));
+}
+
+pub fn run_cleanup(tasks: &HashMap<String, Task>, names: &[String], concurrent_global: bool) {
+    for name in names {
+        if let Some(task) = tasks.get(name) {
+            Logger::cleanup_task(name);
+            run_task(name, task, &[], concurrent_global);
+        }
+    }
+}
+
+/// Runs the commands of a task, with `changed` as its changed files (empty for runs that are not
+/// about a change). Returns `false` when the run was cancelled midway.
+fn run_task(task_name: &String, task: &Task, changed: &[String], concurrent_global: bool) -> bool {
+    let task = &with_changed_files(task, changed);
+
+    if task.service {
+        return service::start(task_name, task);
+    }
+
     let commands = match &task.cmd {
         CommandSpec::Single(s) => vec![s.clone()],
         CommandSpec::Multiple(list) => list.clone(),
     };
 
     let concurrent = task.concurrent.unwrap_or(concurrent_global);
+    // watched runs can be cancelled, which has to reach the whole process tree; other commands
+    // stay in the foreground so they can read the terminal, which delivers Ctrl+C to their tree
+    let own_group = !task.watch.is_empty() || !io::stdin().is_terminal();
 
     if concurrent {
+        let cleanup = signals::is_cleanup_thread();
         let handles: Vec<_> = commands
             .into_iter()
             .map(|cmd_str| {
                 let task_name = task_name.to_owned();
                 let env = task.env.clone();
                 thread::spawn(move || {
-                    execute_command(&task_name, &cmd_str, &env);
+                    signals::set_cleanup_thread(cleanup);
+                    execute_command(&task_name, &cmd_str, &env, own_group)
                 })
             })
             .collect();
 
-        for handle in handles {
-            handle.join().unwrap();
-        }
+        let results: Vec<bool> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        results.into_iter().all(|ok| ok)
     } else {
-        for cmd_str in commands {
-            execute_command(task_name, &cmd_str, &task.env);
-        }
+        commands.iter().all(|cmd_str| execute_command(task_name, cmd_str, &task.env, own_group))
     }
 }
 
-fn execute_command(task_name: &str, cmd_str: &str, env: &HashMap<String, String>) {
+/// Builds the shell invocation for a command. With `own_group`, it leads its own process group, so
+/// it can be stopped along with everything it started; it then runs in the background of the
+/// terminal, and can't read from it.
+pub fn shell_command(cmd_str: &str, env: &HashMap<String, String>, own_group: bool) -> Command {
     #[cfg(unix)]
     let mut command = Command::new("sh");
 
@@ -180,15 +1118,53 @@ fn execute_command(task_name: &str, cmd_str: &str, env: &HashMap<String, String>
         command.arg("/C").arg(cmd_str);
     }
 
+    #[cfg(unix)]
+    if own_group {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    #[cfg(windows)]
+    let _ = own_group;
+
     for (k, v) in env {
         command.env(k, v);
     }
 
+    command
+}
+
+fn execute_command(
+    task_name: &str,
+    cmd_str: &str,
+    env: &HashMap<String, String>,
+    own_group: bool,
+) -> bool {
+    let mut command = shell_command(cmd_str, env, own_group);
+
+    signals::block_if_shutting_down();
+
+    if process::is_stopping(task_name) {
+        return false;
+    }
+
     Logger::command(cmd_str);
-    let status = command.status().expect("command execution failed");
+    let mut child = command.spawn().expect("command execution failed");
+    process::register(child.id(), task_name, own_group);
+    let status = child.wait().expect("command execution failed");
+    process::unregister(child.id());
+
+    if process::was_cancelled(child.id()) {
+        return false;
+    }
 
     if !status.success() {
+        signals::block_if_shutting_down();
+
         Logger::error(&format!("task '{task_name}' failed on: {cmd_str}"));
+        service::stop_all();
         exit(1);
     }
+
+    true
 }
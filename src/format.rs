@@ -1,26 +1,90 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum CommandSpec {
     Single(String),
     Multiple(Vec<String>),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchPolicy {
+    /// Let the current run finish, then run once more for every change made meanwhile.
+    #[default]
+    Queue,
+    /// Cancel the current run (e.g. a dev server, with its whole process tree) and start over.
+    Restart,
+    /// Drop changes made while the task is running.
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// OS notifications (inotify, FSEvents, ReadDirectoryChangesW).
+    #[default]
+    Native,
+    /// Periodic scans, for filesystems that deliver no notifications (NFS, Docker bind mounts...).
+    Poll,
+}
+
+/// What to do when a service task exits on its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    No,
+    OnFailure,
+    Always,
+}
+
+/// How to tell that a service task is up.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadyProbe {
+    /// A TCP port accepts connections (`host:port`, or just the port on localhost).
+    Tcp(String),
+    /// A plain HTTP GET answers with 200.
+    Http(String),
+    /// A line of the service's output matches a regex.
+    Log(String),
+    /// A file exists.
+    File(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Task {
     pub cmd: CommandSpec,
 
     #[serde(default)]
     pub desc: Option<String>,
 
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    #[serde(default)]
+    pub aliases: Vec<String>,
+
+    #[serde(default)]
+    pub private: bool,
+
     #[serde(default)]
     pub deps: Vec<String>,
 
+    #[serde(default)]
+    pub sources: Vec<String>,
+
     #[serde(default)]
     pub watch: Vec<String>,
 
+    #[serde(default)]
+    pub watch_ignore: Vec<String>,
+
+    #[serde(default = "default_true")]
+    pub watch_gitignore: bool,
+
     /*
     #[serde(default)]
     pub cache: bool,
@@ -31,31 +95,121 @@ pub struct Task {
     #[serde(default)]
     pub watch_propagate: bool,
 
+    #[serde(default)]
+    pub watch_deps: bool,
+
+    #[serde(default)]
+    pub watch_policy: WatchPolicy,
+
+    #[serde(default)]
+    pub watch_per_file: bool,
+
+    #[serde(default)]
+    pub watch_hash: bool,
+
+    #[serde(default)]
+    pub watch_mode: Option<WatchMode>,
+
     #[serde(default)]
     pub env: HashMap<String, String>,
 
     #[serde(default)]
     pub concurrent: Option<bool>,
+
+    #[serde(default)]
+    pub service: bool,
+
+    #[serde(default)]
+    pub ready: Option<ReadyProbe>,
+
+    #[serde(default = "default_ready_timeout")]
+    pub ready_timeout: u64,
+
+    #[serde(default)]
+    pub restart: RestartPolicy,
+
+    #[serde(default = "default_restart_max")]
+    pub restart_max: u32,
+
+    #[serde(default = "default_restart_backoff")]
+    pub restart_backoff: u64,
 }
 
 fn default_debounce() -> u64 {
     500
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+fn default_ready_timeout() -> u64 {
+    30000
+}
+
+fn default_restart_max() -> u32 {
+    5
+}
+
+fn default_restart_backoff() -> u64 {
+    1000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Config {
     #[serde(default)]
     pub default: Option<String>,
 
     #[serde(default)]
     pub concurrent: bool,
+
+    #[serde(default)]
+    pub cleanup: Vec<String>,
+
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+
+    #[serde(default)]
+    pub watch_mode: WatchMode,
+
+    #[serde(default = "default_poll_interval")]
+    pub watch_poll_interval: u64,
+
+    #[serde(default)]
+    pub watch_propagate_parallel: bool,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default: None,
+            concurrent: false,
+            cleanup: Vec::new(),
+            shutdown_timeout: default_shutdown_timeout(),
+            watch_mode: WatchMode::default(),
+            watch_poll_interval: default_poll_interval(),
+            watch_propagate_parallel: false,
+        }
+    }
+}
+
+fn default_shutdown_timeout() -> u64 {
+    5000
+}
+
+fn default_poll_interval() -> u64 {
+    500
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct TasksFile {
     #[serde(default)]
     pub config: Config,
 
     #[serde(default)]
     pub tasks: HashMap<String, Task>,
+
+    /// Task names in the order the file declares them.
+    #[serde(skip)]
+    pub order: Vec<String>,
 }
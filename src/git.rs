@@ -0,0 +1,39 @@
+use std::{path::PathBuf, process::Command};
+
+/// Files changed since `reference`, relative to the working directory: committed, staged and
+/// unstaged changes, plus untracked files. Changes are taken from where the current branch forked
+/// off `reference`, so commits made on `reference` since then don't count.
+pub fn changed_files(reference: &str) -> Result<Vec<PathBuf>, String> {
+    git(&["rev-parse", "--git-dir"])?;
+    if git(&["rev-parse", "--verify", "--quiet", &format!("{reference}^{{commit}}")]).is_err() {
+        return Err(format!("unknown git ref: '{reference}'"));
+    }
+
+    let base = git(&["merge-base", reference, "HEAD"])
+        .ok()
+        .and_then(|output| output.lines().next().map(str::to_string))
+        .unwrap_or_else(|| reference.to_string());
+
+    let changed = git(&["diff", "--name-only", "--relative", &base])?;
+    let untracked = git(&["ls-files", "--others", "--exclude-standard"])?;
+
+    let mut files: Vec<PathBuf> =
+        changed.lines().chain(untracked.lines()).map(PathBuf::from).collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+fn git(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|err| format!("failed to run git: {err}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git {} failed: {}", args.join(" "), stderr.trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,211 @@
+use crate::{execution::collect_dependencies, format::Task};
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum GraphFormat {
+    /// A tree of each task and its dependencies.
+    #[default]
+    Ascii,
+    /// Graphviz, e.g. `tazk graph --format dot | dot -Tsvg`.
+    Dot,
+    /// Mermaid flowchart, for Markdown docs.
+    Mermaid,
+}
+
+/// Prints the dependency graph, or the part of it one task needs. Edges point from a dependency to
+/// the task that needs it; the edges of `cycles` are highlighted.
+pub fn print_graph(
+    tasks: &HashMap<String, Task>,
+    task: Option<&str>,
+    format: GraphFormat,
+    details: bool,
+    cycles: &[Vec<String>],
+) {
+    let mut nodes: Vec<&String> = match task {
+        Some(task) => {
+            let deps = collect_dependencies(tasks, task);
+            tasks.keys().filter(|name| deps.contains(*name)).collect()
+        }
+        None => tasks.keys().collect(),
+    };
+    nodes.sort();
+
+    // (task, dep) pairs that close a cycle
+    let cyclic: HashSet<(&str, &str)> = cycles
+        .iter()
+        .flat_map(|cycle| {
+            cycle.iter().zip(cycle.iter().cycle().skip(1)).map(|(a, b)| (a.as_str(), b.as_str()))
+        })
+        .collect();
+
+    let graph = Graph { tasks, nodes, cyclic, details };
+    let output = match format {
+        GraphFormat::Ascii => graph.ascii(task),
+        GraphFormat::Dot => graph.dot(),
+        GraphFormat::Mermaid => graph.mermaid(),
+    };
+    print!("{output}");
+}
+
+struct Graph<'a> {
+    tasks: &'a HashMap<String, Task>,
+    nodes: Vec<&'a String>,
+    cyclic: HashSet<(&'a str, &'a str)>,
+    details: bool,
+}
+
+impl<'a> Graph<'a> {
+    /// (dep, task) pairs, dependencies that are not defined included.
+    fn edges(&self) -> Vec<(&'a str, &'a str)> {
+        self.nodes
+            .iter()
+            .flat_map(|name| self.tasks[*name].deps.iter().map(|dep| (dep.as_str(), name.as_str())))
+            .collect()
+    }
+
+    fn is_cyclic(&self, dep: &str, task: &str) -> bool {
+        self.cyclic.contains(&(task, dep))
+    }
+
+    fn in_cycle(&self, name: &str) -> bool {
+        self.cyclic.iter().any(|(task, _)| *task == name)
+    }
+
+    fn details(&self, name: &str) -> Vec<String> {
+        let Some(task) = self.tasks.get(name).filter(|_| self.details) else {
+            return Vec::new();
+        };
+
+        let mut details = Vec::new();
+        if !task.tags.is_empty() {
+            details.push(format!("tags: {}", task.tags.join(", ")));
+        }
+        let watch = if task.watch.is_empty() { &task.sources } else { &task.watch };
+        if !watch.is_empty() {
+            details.push(format!("watch: {}", watch.join(", ")));
+        }
+        details
+    }
+
+    fn dot(&self) -> String {
+        let mut out = String::from("digraph tasks {\n    rankdir=LR;\n    node [shape=box];\n");
+
+        for name in &self.nodes {
+            let label = [vec![name.to_string()], self.details(name)].concat().join("\\n");
+            let style = if self.in_cycle(name) { ", color=red" } else { "" };
+            out.push_str(&format!("    {name:?} [label={label:?}{style}];\n"));
+        }
+        for (dep, task) in self.edges() {
+            let style = if self.is_cyclic(dep, task) { " [color=red, penwidth=2]" } else { "" };
+            out.push_str(&format!("    {dep:?} -> {task:?}{style};\n"));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    fn mermaid(&self) -> String {
+        // task names may contain characters mermaid ids can't
+        let mut ids: HashMap<&str, String> = HashMap::new();
+        let mut out = String::from("flowchart LR\n");
+
+        for name in &self.nodes {
+            let id = format!("t{}", ids.len());
+            let label = [vec![name.to_string()], self.details(name)].concat().join("<br/>");
+            out.push_str(&format!("    {id}[\"{}\"]\n", label.replace('"', "#quot;")));
+            if self.in_cycle(name) {
+                out.push_str(&format!("    class {id} cycle\n"));
+            }
+            ids.insert(name, id);
+        }
+
+        let mut cyclic_links = Vec::new();
+        for (i, (dep, task)) in self.edges().into_iter().enumerate() {
+            let dep_id = match ids.get(dep) {
+                Some(id) => id.clone(),
+                None => {
+                    let id = format!("t{}", ids.len());
+                    out.push_str(&format!("    {id}[\"{}\"]\n", dep.replace('"', "#quot;")));
+                    ids.insert(dep, id.clone());
+                    id
+                }
+            };
+            out.push_str(&format!("    {dep_id} --> {}\n", ids[task]));
+            if self.is_cyclic(dep, task) {
+                cyclic_links.push(i.to_string());
+            }
+        }
+
+        if !cyclic_links.is_empty() {
+            out.push_str("    classDef cycle stroke:#e33,stroke-width:2px\n");
+            out.push_str(&format!(
+                "    linkStyle {} stroke:#e33,stroke-width:2px\n",
+                cyclic_links.join(",")
+            ));
+        }
+        out
+    }
+
+    /// One tree per task nothing depends on (or just `task`), with its dependencies as children.
+    /// A task that was expanded already is shown again without its dependencies.
+    fn ascii(&self, task: Option<&str>) -> String {
+        let roots: Vec<&str> = match task {
+            Some(task) => vec![task],
+            None => {
+                let needed: HashSet<&str> = self.edges().into_iter().map(|(dep, _)| dep).collect();
+                self.nodes.iter().map(|n| n.as_str()).filter(|n| !needed.contains(n)).collect()
+            }
+        };
+
+        let mut out = String::new();
+        let mut expanded = HashSet::new();
+        for root in roots {
+            self.ascii_node(root, "", "", &mut Vec::new(), &mut expanded, &mut out);
+        }
+        // tasks only reachable through a cycle have no root of their own
+        for name in &self.nodes {
+            if !expanded.contains(name.as_str()) {
+                self.ascii_node(name, "", "", &mut Vec::new(), &mut expanded, &mut out);
+            }
+        }
+        out
+    }
+
+    fn ascii_node(
+        &self,
+        name: &'a str,
+        prefix: &str,
+        branch: &str,
+        path: &mut Vec<&'a str>,
+        expanded: &mut HashSet<&'a str>,
+        out: &mut String,
+    ) {
+        let details = self.details(name);
+        let details =
+            if details.is_empty() { String::new() } else { format!(" ({})", details.join("; ")) };
+
+        if path.contains(&name) {
+            out.push_str(&format!("{prefix}{branch}{name} ↻ cycle\n"));
+            return;
+        }
+        let deps = self.tasks.get(name).map(|t| t.deps.as_slice()).unwrap_or_default();
+        if !expanded.insert(name) && !deps.is_empty() {
+            out.push_str(&format!("{prefix}{branch}{name}{details} …\n"));
+            return;
+        }
+        out.push_str(&format!("{prefix}{branch}{name}{details}\n"));
+
+        let child_prefix = match branch {
+            "" => prefix.to_string(),
+            "└── " => format!("{prefix}    "),
+            _ => format!("{prefix}│   "),
+        };
+        path.push(name);
+        for (i, dep) in deps.iter().enumerate() {
+            let branch = if i + 1 == deps.len() { "└── " } else { "├── " };
+            self.ascii_node(dep, &child_prefix, branch, path, expanded, out);
+        }
+        path.pop();
+    }
+}
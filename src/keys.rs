@@ -0,0 +1,104 @@
+use std::io::{self, BufRead, IsTerminal};
+
+/// Keyboard shortcuts only make sense when both ends of the session are a terminal.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Reads the next key press. On Windows the key has to be followed by Enter.
+pub fn read_key() -> Option<char> {
+    #[cfg(unix)]
+    {
+        use std::io::Read;
+
+        let mut buf = [0u8; 1];
+        loop {
+            match io::stdin().lock().read(&mut buf) {
+                Ok(0) => return None,
+                Ok(_) => return Some(buf[0] as char),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    loop {
+        let line = read_line()?;
+        if let Some(key) = line.chars().next() {
+            return Some(key);
+        }
+    }
+}
+
+/// Reads a whole line with echo, for prompts.
+pub fn read_line() -> Option<String> {
+    disable_raw_input();
+    let mut line = String::new();
+    let read = io::stdin().lock().read_line(&mut line);
+    enable_raw_input();
+
+    match read {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_string()),
+    }
+}
+
+#[cfg(unix)]
+static ORIGINAL: std::sync::Mutex<Option<libc::termios>> = std::sync::Mutex::new(None);
+
+/// Delivers key presses without waiting for Enter and stops echoing them. Output processing and
+/// Ctrl+C are left alone, so child output and signals behave as usual. The terminal is restored
+/// when the process exits, or earlier by dropping a [`RawInput`].
+fn enable_raw_input() {
+    #[cfg(unix)]
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+            return;
+        }
+
+        let mut original = ORIGINAL.lock().unwrap();
+        if original.is_none() {
+            *original = Some(termios);
+            libc::atexit(restore_at_exit);
+        }
+
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+    }
+}
+
+/// Keeps raw input on while alive, and restores the terminal when dropped, unwinding included.
+pub struct RawInput;
+
+impl RawInput {
+    pub fn enable() -> Self {
+        enable_raw_input();
+        Self
+    }
+}
+
+impl Drop for RawInput {
+    fn drop(&mut self) {
+        disable_raw_input();
+    }
+}
+
+fn disable_raw_input() {
+    #[cfg(unix)]
+    if let Ok(original) = ORIGINAL.try_lock()
+        && let Some(termios) = *original
+    {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+        }
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn restore_at_exit() {
+    disable_raw_input();
+}
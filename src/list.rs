@@ -0,0 +1,117 @@
+use crate::{
+    format::{Task, TasksFile},
+    logger::Logger,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+
+// plain comments, so `--help` stays a one-line summary per option
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ListGroup {
+    // the part of the name before the first `:`, e.g. `test` for `test:unit`
+    Namespace,
+    // each tag of the task; tasks with several tags show up under each of them
+    Tag,
+}
+
+/// How `--list` shows the tasks.
+#[derive(Debug, Default)]
+pub struct ListOptions {
+    /// Only the tasks with one of these tags.
+    pub tags: Vec<String>,
+    /// Keep the order of the tasks file instead of sorting by name.
+    pub declared: bool,
+    pub group: Option<ListGroup>,
+    /// Also show the tasks marked `private`.
+    pub private: bool,
+    pub json: bool,
+}
+
+#[derive(Serialize)]
+struct ListedTask<'a> {
+    name: &'a str,
+    desc: Option<&'a String>,
+    aliases: &'a [String],
+    tags: &'a [String],
+    deps: &'a [String],
+    private: bool,
+}
+
+pub fn print_tasks(file: &TasksFile, options: &ListOptions) {
+    let mut names: Vec<&String> = if options.declared {
+        file.order.iter().filter(|name| file.tasks.contains_key(*name)).collect()
+    } else {
+        let mut names: Vec<&String> = file.tasks.keys().collect();
+        names.sort();
+        names
+    };
+    names.retain(|name| {
+        let task = &file.tasks[*name];
+        (options.private || !task.private)
+            && (options.tags.is_empty() || task.tags.iter().any(|t| options.tags.contains(t)))
+    });
+
+    if options.json {
+        let listed: Vec<ListedTask> = names
+            .iter()
+            .map(|name| {
+                let task = &file.tasks[*name];
+                ListedTask {
+                    name,
+                    desc: task.desc.as_ref(),
+                    aliases: &task.aliases,
+                    tags: &task.tags,
+                    deps: &task.deps,
+                    private: task.private,
+                }
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&listed).expect("tasks are serializable"));
+        return;
+    }
+
+    Logger::available_tasks();
+    let Some(group) = options.group else {
+        for name in names {
+            print_task(name, &file.tasks[name]);
+        }
+        return;
+    };
+
+    // ungrouped tasks first, then each group in the order it first shows up
+    let groups_of = |task_name: &str, task: &Task| -> Vec<String> {
+        match group {
+            ListGroup::Namespace => {
+                task_name.split_once(':').map(|(ns, _)| vec![ns.to_string()]).unwrap_or_default()
+            }
+            ListGroup::Tag => task.tags.clone(),
+        }
+    };
+    let mut groups: Vec<String> = Vec::new();
+    for name in &names {
+        for group in groups_of(name, &file.tasks[*name]) {
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+    }
+    if !options.declared {
+        groups.sort();
+    }
+
+    for name in names.iter().filter(|name| groups_of(name, &file.tasks[**name]).is_empty()) {
+        print_task(name, &file.tasks[*name]);
+    }
+    for group in groups {
+        Logger::task_group(&group);
+        for name in
+            names.iter().filter(|name| groups_of(name, &file.tasks[**name]).contains(&group))
+        {
+            print_task(name, &file.tasks[*name]);
+        }
+    }
+}
+
+fn print_task(name: &str, task: &Task) {
+    Logger::task_item(name, task.desc.as_ref(), &task.aliases, &task.deps);
+}
@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 use owo_colors::OwoColorize;
+use std::time::Duration;
 
 pub struct Logger;
 
@@ -74,18 +75,239 @@ impl Logger {
         println!("{} available tasks:", "📋".bold());
     }
 
-    pub fn task_item(name: &str, desc: Option<&String>) {
+    pub fn unknown_task(task_name: &str, suggestions: &[String]) {
+        Logger::error(&format!("unknown task: '{task_name}'"));
+        if !suggestions.is_empty() {
+            eprintln!("   {} {}?", "did you mean:".yellow(), suggestions.join(", ").bold());
+        }
+    }
+
+    pub fn task_item(name: &str, desc: Option<&String>, aliases: &[String], deps: &[String]) {
+        let mut line = format!("   {} {}", "•".cyan().bold(), name.green().bold());
+        if !aliases.is_empty() {
+            line.push_str(&format!(" {}", format!("({})", aliases.join(", ")).green()));
+        }
         if let Some(description) = desc {
-            println!("   {} {}: {}", "•".cyan().bold(), name.green().bold(), description.dimmed());
-        } else {
-            println!("   {} {}", "•".cyan().bold(), name.green().bold());
+            line.push_str(&format!(": {}", description.dimmed()));
+        }
+        if !deps.is_empty() {
+            line.push_str(&format!(" {} {}", "←".blue(), deps.join(", ").blue()));
         }
+        println!("{line}");
+    }
+
+    pub fn task_group(group: &str) {
+        println!("  {} {}", "▸".cyan().bold(), group.bold());
+    }
+
+    pub fn plan_header(runs: usize, skipped: usize, parallel: bool) {
+        let mut summary = format!("{runs} task(s) would run");
+        if skipped > 0 {
+            summary.push_str(&format!(", {skipped} skipped"));
+        }
+        if parallel {
+            summary.push_str(", in parallel");
+        }
+        println!("{} dry run: {}", "📋".bold(), summary.dimmed());
+    }
+
+    pub fn plan_task(index: usize, task_name: &str) {
+        println!("{} {}", format!("{index}.").cyan().bold(), task_name.green().bold());
+    }
+
+    pub fn plan_skipped(task_name: &str, flag: &str) {
+        println!(
+            "{} {} {}",
+            "⏭️".bold(),
+            task_name.dimmed(),
+            format!("(skipped by {flag})").dimmed()
+        );
+    }
+
+    pub fn plan_detail(label: &str, value: &str) {
+        println!("   {} {}", format!("{label}:").blue(), value);
+    }
+
+    pub fn changed_since(reference: &str, files: usize) {
+        println!(
+            "{} {} file(s) changed since {}",
+            "🔀".bold(),
+            files.to_string().bold(),
+            reference.cyan().bold()
+        );
+    }
+
+    pub fn task_affected(task_name: &str, reason: &str) {
+        println!("   {} {}: {}", "✔".green().bold(), task_name.green().bold(), reason.dimmed());
+    }
+
+    pub fn task_unaffected(task_name: &str) {
+        println!("   {} {}: {}", "⏭️".bold(), task_name.dimmed(), "no changes".dimmed());
+    }
+
+    pub fn content_unchanged(task_name: &str) {
+        println!("{} contents unchanged, skipping: {}", "⏭️".bold(), task_name.cyan().bold());
+    }
+
+    pub fn tasks_skipped(tasks: &[String]) {
+        println!("{} skipping: {}", "⏭️".bold(), tasks.join(", ").cyan().bold());
+    }
+
+    pub fn change_ignored(task_name: &str) {
+        println!(
+            "{} task {} is still running, change ignored",
+            "⏭️".bold(),
+            task_name.cyan().bold()
+        );
+    }
+
+    pub fn tasks_file_changed() {
+        println!("{} tasks file changed, reloading...", "📝".bold());
+    }
+
+    pub fn task_reloaded(task_name: &str) {
+        println!("{} task changed, restarting: {}", "🔁".bold(), task_name.cyan().bold());
+    }
+
+    pub fn task_unloaded(task_name: &str) {
+        println!("{} task no longer watched, stopping: {}", "⏹️".bold(), task_name.cyan().bold());
+    }
+
+    pub fn reload_ok() {
+        println!("{} tasks file reloaded", "✅".bold());
+    }
+
+    pub fn reload_rejected() {
+        eprintln!("{} {}", "✗".red().bold(), "keeping the previous tasks".red());
+    }
+
+    pub fn service_ready(task_name: &str, elapsed: Duration) {
+        println!(
+            "{} service ready: {} {}",
+            "🟢".bold(),
+            task_name.cyan().bold(),
+            format!("({}ms)", elapsed.as_millis()).dimmed()
+        );
+    }
+
+    pub fn service_exited(task_name: &str, code: Option<i32>) {
+        let code = code.map_or("a signal".to_string(), |code| format!("code {code}"));
+        Logger::warn(&format!("service '{task_name}' exited with {code}"));
+    }
+
+    pub fn service_restarting(task_name: &str, attempt: u32, max: u32, delay: Duration) {
+        println!(
+            "{} restarting service: {} {}",
+            "🔁".bold(),
+            task_name.cyan().bold(),
+            format!("(attempt {attempt}/{max}, in {}ms)", delay.as_millis()).dimmed()
+        );
+    }
+
+    pub fn service_gave_up(task_name: &str, restarts: u32) {
+        Logger::error(&format!(
+            "service '{task_name}' kept crashing, gave up after {restarts} restart(s)"
+        ));
+    }
+
+    pub fn services_summary() {
+        println!("{} services:", "📋".bold());
+    }
+
+    pub fn service_status(task_name: &str, state: &str, restarts: u32) {
+        let restarts = match restarts {
+            0 => String::new(),
+            1 => " (1 restart)".to_string(),
+            n => format!(" ({n} restarts)"),
+        };
+        println!(
+            "   {} {}: {}{}",
+            "•".cyan().bold(),
+            task_name.green().bold(),
+            state,
+            restarts.dimmed()
+        );
+    }
+
+    pub fn service_stopping(task_name: &str) {
+        println!("{} stopping service: {}", "⏹️".bold(), task_name.cyan().bold());
+    }
+
+    pub fn service_waiting(task_name: &str) {
+        println!(
+            "{} service {} is running... {}",
+            "⏳".bold(),
+            task_name.cyan().bold(),
+            "(Press Ctrl+C to stop)".dimmed()
+        );
     }
 
     pub fn dependency_propagated(task_name: &str) {
         println!("{} propagating to dependent task: {}", "🔄".bold(), task_name.cyan().bold());
     }
 
+    pub fn quitting(running: usize) {
+        println!(
+            "{} quitting, stopping {} running process(es)...",
+            "🛑".bold(),
+            running.to_string().bold()
+        );
+    }
+
+    pub fn keys_hint() {
+        println!("{} press {} to show keyboard shortcuts", "⌨️".bold(), "h".cyan().bold());
+    }
+
+    pub fn keys_help() {
+        println!("{} keyboard shortcuts:", "⌨️".bold());
+        for (key, action) in [
+            ("r", "re-run every task"),
+            ("R", "re-run a single task"),
+            ("c", "clear the screen"),
+            ("p", "pause or resume the watchers"),
+            ("q", "quit"),
+            ("h", "show this help"),
+        ] {
+            println!("   {} {}", key.cyan().bold(), action.dimmed());
+        }
+    }
+
+    pub fn clear_screen() {
+        print!("\x1b[2J\x1b[H");
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+    }
+
+    pub fn watch_paused(paused: bool) {
+        if paused {
+            println!("{} watchers paused, press {} to resume", "⏸️".bold(), "p".cyan().bold());
+        } else {
+            println!("{} watchers resumed", "▶️".bold());
+        }
+    }
+
+    pub fn pick_task(tasks: &[String]) {
+        println!("{} pick a task to re-run:", "📋".bold());
+        for (i, name) in tasks.iter().enumerate() {
+            println!("   {} {}", format!("{}.", i + 1).cyan().bold(), name.green().bold());
+        }
+        print!("{} ", "➜".blue().bold());
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+    }
+
+    pub fn interrupted(signal: &str, running: usize) {
+        println!();
+        println!(
+            "{} received {}, stopping {} running process(es)...",
+            "🛑".bold(),
+            signal.yellow().bold(),
+            running.to_string().bold()
+        );
+    }
+
+    pub fn cleanup_task(task_name: &str) {
+        println!("{} running cleanup task: {}", "🧹".bold(), task_name.cyan().bold());
+    }
+
     pub fn debug(msg: &str) {
         if cfg!(debug_assertions) {
             println!("{} {}", "🔍".dimmed(), msg.dimmed());
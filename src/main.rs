@@ -1,40 +1,169 @@
+mod dry_run;
 mod execution;
 mod format;
+mod git;
+mod graph;
+mod keys;
+mod list;
 mod logger;
+mod process;
+mod query;
+mod service;
+mod signals;
 mod tasks_file;
 mod watch;
 
 use crate::{
-    execution::run_from_task,
-    format::TasksFile,
+    execution::{Selection, WatchOverride, plan, run_cleanup, run_from_task},
+    format::{Task, TasksFile},
+    graph::GraphFormat,
+    list::{ListGroup, ListOptions, print_tasks},
     logger::Logger,
-    tasks_file::{ValidationError, detect_tasks_file, parse_tasks_file, validate_tasks_file},
+    query::Cause,
+    tasks_file::{
+        detect_cycles, detect_tasks_file, matching_tasks, parse_tasks_file, resolve_task,
+        suggest_tasks, tagged_tasks, validate_tasks_file,
+    },
+};
+use clap::{ArgGroup, Parser, Subcommand};
+use glob::Pattern;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    process::exit,
+    time::Duration,
 };
-use clap::Parser;
-use std::{path::PathBuf, process::exit};
 
 #[derive(Parser)]
 #[command(
     name = env!("CARGO_PKG_NAME"),
     version = env!("CARGO_PKG_VERSION"),
-    about = env!("CARGO_PKG_DESCRIPTION")
+    about = env!("CARGO_PKG_DESCRIPTION"),
+    group(ArgGroup::new("output").args(["list", "dry_run"]).multiple(true))
 )]
 struct Cli {
-    task: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Tasks to run (uses default if not specified)
+    tasks: Vec<String>,
 
+    /// Use a specific tasks file
     #[arg(long, short)]
     file: Option<String>,
 
+    /// List all available tasks
     #[arg(long, short)]
     list: bool,
+
+    /// With --list, keep the order of the tasks file instead of sorting by name
+    #[arg(long, requires = "list")]
+    declared: bool,
+
+    /// With --list, group the tasks by namespace (`ns:task`) or tag
+    #[arg(long, value_enum, value_name = "BY", requires = "list")]
+    group: Option<ListGroup>,
+
+    /// With --list, also show private tasks
+    #[arg(long, requires = "list")]
+    private: bool,
+
+    /// Watch the task's `sources`, or the given patterns
+    #[arg(long, short, num_args = 0..=1, require_equals = true, value_name = "GLOB")]
+    watch: Option<Vec<String>>,
+
+    /// Also watch the files of every dependency
+    #[arg(long)]
+    watch_deps: bool,
+
+    /// Run the given tasks in parallel, each as soon as its dependencies are done
+    #[arg(long, short)]
+    parallel: bool,
+
+    /// Run (or list) the tasks with this tag
+    #[arg(long, short, value_name = "TAG")]
+    tag: Vec<String>,
+
+    /// Run every task whose name contains the pattern (or matches it, with wildcards)
+    #[arg(long, value_name = "PATTERN")]
+    all_matching: Option<String>,
+
+    /// Run only the given tasks, without their dependencies
+    #[arg(long)]
+    no_deps: bool,
+
+    /// Run only these tasks of the plan
+    #[arg(long, value_delimiter = ',', value_name = "TASKS")]
+    only: Vec<String>,
+
+    /// Resume the plan from this task, e.g. after it failed
+    #[arg(long, value_name = "TASK")]
+    from: Option<String>,
+
+    /// Leave these tasks out of the plan
+    #[arg(long, value_delimiter = ',', value_name = "TASKS")]
+    skip: Vec<String>,
+
+    /// Run only the tasks affected by the files changed since this git ref
+    #[arg(long, value_name = "REF")]
+    since: Option<String>,
+
+    /// Show what would run, without running anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Print the plan (with --dry-run) or the task list (with --list) as JSON
+    #[arg(long, requires = "output")]
+    json: bool,
 }
 
-fn main() {
-    Logger::banner();
+#[derive(Subcommand)]
+enum Command {
+    /// Print the dependency graph, or the part of it a task needs
+    Graph {
+        /// Only this task and its dependencies
+        task: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        format: GraphFormat,
+
+        /// Show the tags and watch patterns of each task
+        #[arg(long)]
+        details: bool,
+    },
 
+    /// Print every dependency path from one task to another
+    Why {
+        /// The task that needs the other one
+        from: String,
+
+        /// The dependency to explain
+        to: String,
+    },
+
+    /// List the tasks whose `watch` or `sources` match the files, plus their dependents
+    Affected {
+        /// Changed files, e.g. from `git diff --name-only`
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Show why each task is affected
+        #[arg(long)]
+        explain: bool,
+    },
+}
+
+fn main() {
     let cli = Cli::parse();
 
-    let tasks_file = match cli.file {
+    // keep stdout parseable for JSON output and the graph
+    let quiet = ((cli.dry_run || cli.list) && cli.json) || cli.command.is_some();
+    if !quiet {
+        Logger::banner();
+    }
+
+    let tasks_file = match cli.file.clone() {
         Some(file) => {
             let path = PathBuf::from(file);
             if !path.exists() {
@@ -49,55 +178,283 @@ fn main() {
         }),
     };
 
-    Logger::file_path(&tasks_file.display().to_string());
+    if !quiet {
+        Logger::file_path(&tasks_file.display().to_string());
+    }
 
-    let file_parsed: TasksFile = parse_tasks_file(tasks_file);
+    let file_parsed: TasksFile = parse_tasks_file(tasks_file.clone());
     let concurrent_global = file_parsed.config.concurrent;
 
     let errors = validate_tasks_file(file_parsed.clone());
     if !errors.is_empty() {
         Logger::error("validation errors found:");
-        for error in errors {
-            match error {
-                ValidationError::DuplicatedTask(name) => {
-                    Logger::validation_error(&format!("duplicated task name: {name}"));
-                }
-                ValidationError::DependencyNotFound { task, dep } => {
-                    Logger::validation_error(&format!(
-                        "task '{task}' has a missing dependency: '{dep}'"
-                    ));
-                }
-                ValidationError::EmptyCommand(name) => {
-                    Logger::validation_error(&format!("task '{name}' has an empty command"));
-                }
-                ValidationError::SelfDependency(name) => {
-                    Logger::validation_error(&format!("task '{name}' has a self-dependency"));
-                }
-                ValidationError::CyclicDependency { cycle } => {
-                    Logger::validation_error(&format!(
-                        "cyclic dependency detected: {}",
-                        cycle.join(" → ")
-                    ));
-                }
-            }
+        for error in &errors {
+            Logger::validation_error(&error.to_string());
         }
-        exit(1);
+        // the graph is still drawn, to show where the cycles are
+        if !matches!(cli.command, Some(Command::Graph { .. })) {
+            exit(1);
+        }
+    } else if !quiet {
+        Logger::validation_ok();
+    }
+
+    if let Some(Command::Graph { task, format, details }) = &cli.command {
+        let task = task.as_ref().map(|task| known_task(task, &file_parsed.tasks));
+
+        let cycles = detect_cycles(&file_parsed.tasks);
+        graph::print_graph(&file_parsed.tasks, task.as_deref(), *format, *details, &cycles);
+        exit(if errors.is_empty() { 0 } else { 1 });
     }
 
-    Logger::validation_ok();
+    match &cli.command {
+        Some(Command::Why { from, to }) => {
+            let from = known_task(from, &file_parsed.tasks);
+            let to = known_task(to, &file_parsed.tasks);
+
+            if !query::print_why(&file_parsed.tasks, &from, &to) {
+                Logger::error(&format!("'{from}' does not depend on '{to}'"));
+                exit(1);
+            }
+            exit(0);
+        }
+        Some(Command::Affected { files, explain }) => {
+            query::print_affected(&file_parsed.tasks, files, *explain);
+            exit(0);
+        }
+        _ => {}
+    }
 
     if cli.list {
-        Logger::separator();
-        Logger::available_tasks();
-        for (name, task) in file_parsed.tasks {
-            Logger::task_item(&name, task.desc.as_ref());
+        let options = ListOptions {
+            tags: cli.tag.clone(),
+            declared: cli.declared,
+            group: cli.group,
+            private: cli.private,
+            json: cli.json,
+        };
+
+        if !quiet {
+            Logger::separator();
+        }
+        print_tasks(&file_parsed, &options);
+        if !quiet {
+            Logger::separator();
         }
-        Logger::separator();
         exit(0);
     }
 
-    let task_name = cli.task.clone().or(file_parsed.config.default.clone()).unwrap_or_default();
+    let targets = select_targets(&cli, &file_parsed);
+    let selection = select_plan(&cli, &file_parsed, &targets, quiet);
+
+    let watch_override = (cli.watch.is_some() || cli.watch_deps).then(|| WatchOverride {
+        globs: cli.watch.clone().unwrap_or_default(),
+        deps: cli.watch_deps,
+    });
+
+    if cli.dry_run {
+        if !quiet {
+            Logger::separator();
+        }
+        dry_run::print_plan(
+            &file_parsed.tasks,
+            &targets,
+            &selection,
+            watch_override.as_ref(),
+            cli.parallel,
+            concurrent_global,
+            cli.json,
+        );
+        if !quiet {
+            Logger::separator();
+        }
+        exit(0);
+    }
+
+    let cleanup_tasks = file_parsed.tasks.clone();
+    let cleanup = file_parsed.config.cleanup.clone();
+    signals::install(Duration::from_millis(file_parsed.config.shutdown_timeout), move || {
+        run_cleanup(&cleanup_tasks, &cleanup, concurrent_global);
+        service::stop_all();
+    });
 
     Logger::separator();
-    run_from_task(&file_parsed.tasks, &task_name, concurrent_global);
+
+    run_from_task(
+        &file_parsed.tasks,
+        &targets,
+        cli.parallel,
+        &file_parsed.config,
+        &tasks_file,
+        watch_override,
+        selection,
+    );
+}
+
+/// The task called `name` or having it as an alias; exits with a hint when there is none.
+fn known_task(name: &str, tasks: &HashMap<String, Task>) -> String {
+    resolve_task(name, tasks).unwrap_or_else(|| {
+        Logger::unknown_task(name, &suggest_tasks(name, tasks));
+        exit(1);
+    })
+}
+
+/// Resolves the task names, wildcards, `--tag` and `--all-matching` into the tasks to run, in the
+/// order they were given. Falls back to `config.default` when nothing was selected.
+fn select_targets(cli: &Cli, file: &TasksFile) -> Vec<String> {
+    let tasks = &file.tasks;
+    let mut targets = Vec::new();
+    let mut failed = false;
+
+    for arg in &cli.tasks {
+        if let Some(task) = resolve_task(arg, tasks) {
+            targets.push(task);
+            continue;
+        }
+
+        let matching = if is_wildcard(arg) { matching_tasks(arg, tasks) } else { Vec::new() };
+        if matching.is_empty() {
+            if is_wildcard(arg) {
+                Logger::error(&format!("no tasks match '{arg}'"));
+            } else {
+                Logger::unknown_task(arg, &suggest_tasks(arg, tasks));
+            }
+            failed = true;
+        }
+        targets.extend(matching);
+    }
+
+    if !cli.tag.is_empty() {
+        let tagged = tagged_tasks(&cli.tag, tasks);
+        if tagged.is_empty() {
+            Logger::error(&format!("no tasks are tagged {}", cli.tag.join(", ")));
+            failed = true;
+        }
+        targets.extend(tagged);
+    }
+
+    if let Some(pattern) = &cli.all_matching {
+        let pattern = if is_wildcard(pattern) {
+            pattern.clone()
+        } else {
+            format!("*{}*", Pattern::escape(pattern))
+        };
+        let matching = matching_tasks(&pattern, tasks);
+        if matching.is_empty() {
+            Logger::error(&format!("no tasks match '{pattern}'"));
+            failed = true;
+        }
+        targets.extend(matching);
+    }
+
+    let selected = !cli.tasks.is_empty() || !cli.tag.is_empty() || cli.all_matching.is_some();
+    if !selected {
+        match &file.config.default {
+            Some(default) if tasks.contains_key(default) => targets.push(default.clone()),
+            Some(default) => {
+                Logger::unknown_task(default, &suggest_tasks(default, tasks));
+                failed = true;
+            }
+            None => {
+                Logger::error("no task given and no `default` task set in [config]");
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        print_tasks(file, &ListOptions::default());
+        exit(1);
+    }
+
+    let mut seen = HashSet::new();
+    targets.retain(|t| seen.insert(t.clone()));
+    targets
+}
+
+/// Collects `--no-deps`, `--only`, `--from` and `--skip`, making sure they name tasks of the plan.
+/// Aliases are resolved to the names of their tasks.
+fn select_plan(cli: &Cli, file: &TasksFile, targets: &[String], quiet: bool) -> Selection {
+    let tasks = &file.tasks;
+    let mut failed = false;
+    let mut resolve = |names: &[String]| -> Vec<String> {
+        names
+            .iter()
+            .filter_map(|name| {
+                let task = resolve_task(name, tasks);
+                if task.is_none() {
+                    Logger::unknown_task(name, &suggest_tasks(name, tasks));
+                    failed = true;
+                }
+                task
+            })
+            .collect()
+    };
+
+    let only = resolve(&cli.only);
+    let from = resolve(cli.from.as_slice()).pop();
+    let skip = resolve(&cli.skip);
+
+    if failed {
+        print_tasks(file, &ListOptions::default());
+        exit(1);
+    }
+
+    // a task outside the plan is most likely a typo, which must not pass as an empty run
+    let planned = plan(tasks, targets);
+    let checks: [(&[String], &str); 3] = [
+        (&only, "cannot run only it"),
+        (&skip, "cannot skip it"),
+        (from.as_slice(), "cannot resume from it"),
+    ];
+    let mut outside = false;
+    for (names, reason) in checks {
+        for name in names.iter().filter(|name| !planned.contains(name)) {
+            Logger::error(&format!("'{name}' is not part of the run, {reason}"));
+            outside = true;
+        }
+    }
+    if outside {
+        exit(1);
+    }
+
+    Selection {
+        no_deps: cli.no_deps,
+        only,
+        from,
+        skip,
+        since: cli.since.as_ref().map(|reference| affected_since(reference, tasks, targets, quiet)),
+    }
+}
+
+/// The tasks of the plan affected by the files changed since a git ref, explaining why each task
+/// of the plan runs or not.
+fn affected_since(
+    reference: &str,
+    tasks: &HashMap<String, Task>,
+    targets: &[String],
+    quiet: bool,
+) -> HashSet<String> {
+    let files = git::changed_files(reference).unwrap_or_else(|err| {
+        Logger::error(&err);
+        exit(1);
+    });
+    let mut affected: HashMap<String, Cause> =
+        query::affected_tasks(tasks, &files).into_iter().collect();
+
+    if !quiet {
+        Logger::changed_since(reference, files.len());
+        for name in plan(tasks, targets) {
+            match affected.get(&name) {
+                Some(cause) => Logger::task_affected(&name, &cause.to_string()),
+                None => Logger::task_unaffected(&name),
+            }
+        }
+    }
+
+    affected.drain().map(|(name, _)| name).collect()
+}
+
+fn is_wildcard(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
 }
@@ -0,0 +1,158 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{LazyLock, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+// pid -> task name
+static CHILDREN: LazyLock<Mutex<HashMap<u32, String>>> = LazyLock::new(Default::default);
+
+// children that lead their own process group
+static GROUP_LEADERS: LazyLock<Mutex<HashSet<u32>>> = LazyLock::new(Default::default);
+
+// children stopped on purpose, whose exit status is not a failure
+static CANCELLED: LazyLock<Mutex<HashSet<u32>>> = LazyLock::new(Default::default);
+
+// tasks whose current run is being cancelled, so they must not start new commands
+static STOPPING: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+pub fn register(pid: u32, task_name: &str, own_group: bool) {
+    CHILDREN.lock().unwrap().insert(pid, task_name.to_string());
+    if own_group {
+        GROUP_LEADERS.lock().unwrap().insert(pid);
+    }
+}
+
+pub fn unregister(pid: u32) {
+    CHILDREN.lock().unwrap().remove(&pid);
+    GROUP_LEADERS.lock().unwrap().remove(&pid);
+}
+
+pub fn running() -> Vec<u32> {
+    CHILDREN.lock().unwrap().keys().copied().collect()
+}
+
+pub fn running_for(task_name: &str) -> Vec<u32> {
+    CHILDREN
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, name)| *name == task_name)
+        .map(|(pid, _)| *pid)
+        .collect()
+}
+
+pub fn was_cancelled(pid: u32) -> bool {
+    CANCELLED.lock().unwrap().remove(&pid)
+}
+
+pub fn is_stopping(task_name: &str) -> bool {
+    STOPPING.lock().unwrap().contains(task_name)
+}
+
+pub fn resume_task(task_name: &str) {
+    STOPPING.lock().unwrap().remove(task_name);
+}
+
+/// Sends `signal` to the process group led by `pid`, which reaches the whole tree the shell
+/// started. A command left in the foreground group only gets it itself; Ctrl+C reaches its own
+/// children through the terminal.
+#[cfg(unix)]
+pub fn signal_group(pid: u32, signal: i32) {
+    let own_group = GROUP_LEADERS.lock().unwrap().contains(&pid);
+    unsafe {
+        if own_group {
+            libc::killpg(pid as libc::pid_t, signal);
+        } else {
+            libc::kill(pid as libc::pid_t, signal);
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn kill_tree(pid: u32) {
+    let _ = std::process::Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+}
+
+/// Forwards `signal` to every running child and waits up to `timeout` for them to exit. Whatever
+/// is still alive afterwards is killed.
+pub fn terminate_all(signal: i32, timeout: Duration) {
+    #[cfg(unix)]
+    for pid in running() {
+        signal_group(pid, signal);
+    }
+
+    #[cfg(windows)]
+    let _ = signal;
+
+    let deadline = Instant::now() + timeout;
+    while !running().is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    kill_all();
+}
+
+/// Cancels the current run of `task_name`: its running commands (the whole process tree) are
+/// stopped, killed after `timeout`, and no further commands start until [`resume_task`]. The
+/// stopped commands are not reported as failures.
+pub fn stop_task(task_name: &str, timeout: Duration) {
+    let pids = signal_stop(task_name);
+    wait_or_kill(&pids, timeout);
+}
+
+/// Like [`stop_task`], without waiting: the commands are asked to stop right away, and killed
+/// after `timeout` from a thread of their own.
+pub fn cancel_task(task_name: &str, timeout: Duration) {
+    let pids = signal_stop(task_name);
+    if !pids.is_empty() {
+        thread::spawn(move || wait_or_kill(&pids, timeout));
+    }
+}
+
+fn signal_stop(task_name: &str) -> Vec<u32> {
+    STOPPING.lock().unwrap().insert(task_name.to_string());
+
+    let pids = running_for(task_name);
+    CANCELLED.lock().unwrap().extend(&pids);
+
+    for pid in &pids {
+        #[cfg(unix)]
+        signal_group(*pid, libc::SIGTERM);
+
+        #[cfg(windows)]
+        kill_tree(*pid);
+    }
+    pids
+}
+
+fn wait_or_kill(pids: &[u32], timeout: Duration) {
+    let alive = |pid: &u32| CHILDREN.lock().unwrap().contains_key(pid);
+    let deadline = Instant::now() + timeout;
+    while pids.iter().any(alive) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    #[cfg(unix)]
+    for pid in pids.iter().filter(|pid| alive(pid)) {
+        signal_group(*pid, libc::SIGKILL);
+    }
+    while pids.iter().any(alive) {
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+pub fn kill_all() {
+    for pid in running() {
+        #[cfg(unix)]
+        signal_group(pid, libc::SIGKILL);
+
+        #[cfg(windows)]
+        kill_tree(pid);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{
+    execution::{collect_dependents, topological_order},
+    format::Task,
+    watch::relative,
+};
+use glob::Pattern;
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+/// Why a task is affected by a set of changed files.
+#[derive(Debug, Clone)]
+pub enum Cause {
+    /// One of its own `watch` or `sources` patterns matched a file.
+    Matched { file: String, pattern: String },
+    /// It depends, directly or transitively, on a task whose files changed.
+    Dependency(String),
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::Matched { file, pattern } => write!(f, "{file} matches '{pattern}'"),
+            Cause::Dependency(task) => write!(f, "depends on '{task}'"),
+        }
+    }
+}
+
+/// Every dependency path from `from` down to `to`, each starting with `from` and ending with `to`.
+pub fn dependency_paths(tasks: &HashMap<String, Task>, from: &str, to: &str) -> Vec<Vec<String>> {
+    fn dfs(
+        task: &str,
+        to: &str,
+        tasks: &HashMap<String, Task>,
+        path: &mut Vec<String>,
+        paths: &mut Vec<Vec<String>>,
+    ) {
+        // a cycle never leads anywhere new
+        if path.iter().any(|t| t == task) {
+            return;
+        }
+
+        path.push(task.to_string());
+        if task == to {
+            paths.push(path.clone());
+        } else if let Some(t) = tasks.get(task) {
+            for dep in &t.deps {
+                dfs(dep, to, tasks, path, paths);
+            }
+        }
+        path.pop();
+    }
+
+    let mut paths = Vec::new();
+    dfs(from, to, tasks, &mut Vec::new(), &mut paths);
+    paths
+}
+
+/// The tasks whose `watch` or `sources` patterns match one of the files, plus everything that
+/// depends on them, in dependency order. Relative files are taken from the working directory,
+/// like the patterns.
+pub fn affected_tasks(tasks: &HashMap<String, Task>, files: &[PathBuf]) -> Vec<(String, Cause)> {
+    let files: Vec<PathBuf> = files.iter().map(|file| relative(file)).collect();
+    let order = topological_order(tasks);
+
+    let mut causes: HashMap<String, Cause> = HashMap::new();
+    for name in &order {
+        if let Some(cause) = matched_file(&tasks[name], &files) {
+            causes.insert(name.clone(), cause);
+        }
+    }
+
+    // tasks are visited in dependency order, so dependents get the first cause upstream of them
+    for name in &order {
+        if !matches!(causes.get(name), Some(Cause::Matched { .. })) {
+            continue;
+        }
+        for dependent in collect_dependents(tasks, name) {
+            causes.entry(dependent).or_insert_with(|| Cause::Dependency(name.clone()));
+        }
+    }
+
+    order.into_iter().filter_map(|name| causes.remove(&name).map(|cause| (name, cause))).collect()
+}
+
+/// The first file one of the task's patterns matches, unless a `!pattern` or `watch_ignore`
+/// entry excludes it.
+fn matched_file(task: &Task, files: &[PathBuf]) -> Option<Cause> {
+    let (excluded, included): (Vec<&String>, Vec<&String>) =
+        task.watch.iter().chain(&task.sources).partition(|p| p.starts_with('!'));
+    let excluded: Vec<Pattern> = excluded
+        .iter()
+        .map(|p| &p[1..])
+        .chain(task.watch_ignore.iter().map(String::as_str))
+        .filter_map(|p| Pattern::new(p).ok())
+        .collect();
+
+    files.iter().filter(|file| !excluded.iter().any(|p| p.matches_path(file))).find_map(|file| {
+        included.iter().find(|p| Pattern::new(p).is_ok_and(|p| p.matches_path(file))).map(
+            |pattern| Cause::Matched {
+                file: file.display().to_string(),
+                pattern: pattern.to_string(),
+            },
+        )
+    })
+}
+
+/// Prints every dependency path from `from` to `to`. Returns false if there is none.
+pub fn print_why(tasks: &HashMap<String, Task>, from: &str, to: &str) -> bool {
+    let paths = dependency_paths(tasks, from, to);
+    for path in &paths {
+        println!("{}", path.join(" → "));
+    }
+    !paths.is_empty()
+}
+
+/// Prints the affected tasks one per line, so the output can be handed back to tazk.
+pub fn print_affected(tasks: &HashMap<String, Task>, files: &[PathBuf], explain: bool) {
+    for (name, cause) in affected_tasks(tasks, files) {
+        if explain {
+            println!("{name}: {cause}");
+        } else {
+            println!("{name}");
+        }
+    }
+}
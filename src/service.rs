@@ -0,0 +1,342 @@
+use crate::{
+    execution::shell_command,
+    format::{CommandSpec, ReadyProbe, RestartPolicy, Task},
+    logger::Logger,
+    process, signals,
+};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    process::{ExitStatus, Stdio, exit},
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Starting,
+    Ready,
+    Restarting,
+    Exited(Option<i32>),
+    GaveUp,
+}
+
+struct Service {
+    // bumped on every launch, so exits of a replaced instance are not mistaken for crashes
+    generation: u64,
+    restarts: u32,
+    state: State,
+}
+
+// service tasks started during the session, torn down when it ends
+static SERVICES: LazyLock<Mutex<HashMap<String, Service>>> = LazyLock::new(Default::default);
+
+enum Launch {
+    Ready,
+    Cancelled,
+    Failed(String),
+}
+
+/// Starts the commands of a service task in the background and blocks until its readiness probe
+/// passes, so dependent tasks only start once it is up. A running instance of the same service is
+/// replaced. Returns false if the start was cancelled.
+pub fn start(task_name: &str, task: &Task) -> bool {
+    match launch(task_name, task, 0) {
+        Launch::Ready => true,
+        Launch::Cancelled => false,
+        Launch::Failed(reason) => fail(task_name, &reason),
+    }
+}
+
+fn launch(task_name: &str, task: &Task, restarts: u32) -> Launch {
+    if !process::running_for(task_name).is_empty() {
+        process::stop_task(task_name, signals::shutdown_timeout());
+        process::resume_task(task_name);
+    }
+
+    let commands = match &task.cmd {
+        CommandSpec::Single(s) => vec![s.clone()],
+        CommandSpec::Multiple(list) => list.clone(),
+    };
+
+    let log_pattern = match &task.ready {
+        Some(ReadyProbe::Log(pattern)) => Some(Regex::new(pattern).expect("invalid ready pattern")),
+        _ => None,
+    };
+
+    signals::block_if_shutting_down();
+    if process::is_stopping(task_name) {
+        return Launch::Cancelled;
+    }
+
+    let generation = {
+        let mut services = SERVICES.lock().unwrap();
+        let service = services.entry(task_name.to_string()).or_insert(Service {
+            generation: 0,
+            restarts: 0,
+            state: State::Starting,
+        });
+        service.generation += 1;
+        service.restarts = restarts;
+        service.state = State::Starting;
+        service.generation
+    };
+
+    // set once the probe passes / when a command exits on its own
+    let up = Arc::new(AtomicBool::new(false));
+    let exited = Arc::new(AtomicBool::new(false));
+    let log_matched = Arc::new(AtomicBool::new(false));
+
+    for cmd_str in &commands {
+        let mut command = shell_command(cmd_str, &task.env, true);
+        if log_pattern.is_some() {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+
+        Logger::command(cmd_str);
+        let mut child = command.spawn().expect("command execution failed");
+        let pid = child.id();
+        process::register(pid, task_name, true);
+
+        if let Some(pattern) = &log_pattern {
+            if let Some(stdout) = child.stdout.take() {
+                forward_output(stdout, pattern.clone(), log_matched.clone(), false);
+            }
+            if let Some(stderr) = child.stderr.take() {
+                forward_output(stderr, pattern.clone(), log_matched.clone(), true);
+            }
+        }
+
+        let task_name = task_name.to_string();
+        let task = task.clone();
+        let (up, exited) = (up.clone(), exited.clone());
+        thread::spawn(move || {
+            let status = child.wait().expect("command execution failed");
+            process::unregister(pid);
+
+            if process::was_cancelled(pid) || signals::is_shutting_down() {
+                return;
+            }
+
+            exited.store(true, Ordering::SeqCst);
+            if up.load(Ordering::SeqCst) {
+                on_exit(&task_name, &task, generation, status);
+            }
+        });
+    }
+
+    let started = Instant::now();
+    let timeout = Duration::from_millis(task.ready_timeout);
+    loop {
+        if process::is_stopping(task_name) {
+            return Launch::Cancelled;
+        }
+
+        if exited.load(Ordering::SeqCst) {
+            return Launch::Failed("exited before becoming ready".to_string());
+        }
+
+        let ready = match &task.ready {
+            None => true,
+            Some(ReadyProbe::Tcp(address)) => tcp_open(address),
+            Some(ReadyProbe::Http(url)) => http_ok(url),
+            Some(ReadyProbe::Log(_)) => log_matched.load(Ordering::SeqCst),
+            Some(ReadyProbe::File(path)) => Path::new(path).exists(),
+        };
+        if ready {
+            break;
+        }
+
+        if started.elapsed() >= timeout {
+            return Launch::Failed(format!("did not become ready within {}ms", task.ready_timeout));
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    up.store(true, Ordering::SeqCst);
+    if let Some(service) = SERVICES.lock().unwrap().get_mut(task_name) {
+        service.state = State::Ready;
+    }
+    Logger::service_ready(task_name, started.elapsed());
+    Launch::Ready
+}
+
+/// Applies the `restart` policy to a service that exited on its own after becoming ready.
+fn on_exit(task_name: &str, task: &Task, generation: u64, status: ExitStatus) {
+    let restart = match task.restart {
+        RestartPolicy::No => false,
+        RestartPolicy::OnFailure => !status.success(),
+        RestartPolicy::Always => true,
+    };
+
+    let mut services = SERVICES.lock().unwrap();
+    let Some(service) = services.get_mut(task_name).filter(|s| s.generation == generation) else {
+        return;
+    };
+
+    Logger::service_exited(task_name, status.code());
+    if !restart {
+        service.state = State::Exited(status.code());
+        return;
+    }
+
+    // the instance is done for, its other commands must not trigger restarts of their own
+    service.generation += 1;
+    let attempt = service.restarts + 1;
+    drop(services);
+
+    restart_service(task_name, task, attempt);
+}
+
+fn restart_service(task_name: &str, task: &Task, attempt: u32) {
+    let generation = {
+        let mut services = SERVICES.lock().unwrap();
+        let Some(service) = services.get_mut(task_name) else {
+            return;
+        };
+
+        if attempt > task.restart_max {
+            service.state = State::GaveUp;
+            drop(services);
+
+            Logger::service_gave_up(task_name, task.restart_max);
+            process::stop_task(task_name, signals::shutdown_timeout());
+            process::resume_task(task_name);
+            return;
+        }
+
+        service.state = State::Restarting;
+        service.generation
+    };
+
+    // exponential backoff, capped at 30s
+    let factor = 1u64 << (attempt - 1).min(16);
+    let delay = Duration::from_millis(task.restart_backoff.saturating_mul(factor))
+        .min(Duration::from_secs(30));
+    Logger::service_restarting(task_name, attempt, task.restart_max, delay);
+    thread::sleep(delay);
+
+    // stopped, or started again by something else in the meantime
+    let current = SERVICES.lock().unwrap().get(task_name).map(|s| s.generation);
+    if signals::is_shutting_down() || current != Some(generation) {
+        return;
+    }
+
+    if let Launch::Failed(reason) = launch(task_name, task, attempt) {
+        Logger::error(&format!("service '{task_name}' {reason}"));
+        restart_service(task_name, task, attempt + 1);
+    }
+}
+
+/// Whether a service is running or about to be restarted.
+pub fn is_up(task_name: &str) -> bool {
+    SERVICES
+        .lock()
+        .unwrap()
+        .get(task_name)
+        .is_some_and(|s| matches!(s.state, State::Starting | State::Ready | State::Restarting))
+}
+
+/// Stops every service started during the session and reports how each of them ended.
+pub fn stop_all() {
+    let services: Vec<(String, Service)> = SERVICES.lock().unwrap().drain().collect();
+    if services.is_empty() {
+        return;
+    }
+
+    for (task_name, _) in &services {
+        if !process::running_for(task_name).is_empty() {
+            Logger::service_stopping(task_name);
+            process::stop_task(task_name, signals::shutdown_timeout());
+        }
+    }
+
+    Logger::services_summary();
+    for (task_name, service) in services {
+        let state = match service.state {
+            State::Exited(Some(code)) => format!("exited with code {code}"),
+            State::Exited(None) => "killed by a signal".to_string(),
+            State::GaveUp => "crashed, gave up restarting".to_string(),
+            State::Starting | State::Ready | State::Restarting => "stopped".to_string(),
+        };
+        Logger::service_status(&task_name, &state, service.restarts);
+    }
+}
+
+fn fail(task_name: &str, reason: &str) -> ! {
+    signals::block_if_shutting_down();
+
+    Logger::error(&format!("service '{task_name}' {reason}"));
+    stop_all();
+    exit(1);
+}
+
+/// Passes the output of a service through, watching for the line that marks it ready.
+fn forward_output<R: Read + Send + 'static>(
+    output: R,
+    pattern: Regex,
+    matched: Arc<AtomicBool>,
+    stderr: bool,
+) {
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines().map_while(Result::ok) {
+            if stderr {
+                eprintln!("{line}");
+            } else {
+                println!("{line}");
+            }
+
+            if !matched.load(Ordering::SeqCst) && pattern.is_match(&line) {
+                matched.store(true, Ordering::SeqCst);
+            }
+        }
+    });
+}
+
+fn connect(address: &str) -> Option<TcpStream> {
+    let address = match address.parse::<u16>() {
+        Ok(port) => format!("localhost:{port}"),
+        Err(_) => address.to_string(),
+    };
+
+    address
+        .to_socket_addrs()
+        .ok()?
+        .find_map(|addr| TcpStream::connect_timeout(&addr, Duration::from_millis(500)).ok())
+}
+
+fn tcp_open(address: &str) -> bool {
+    connect(address).is_some()
+}
+
+/// Sends a plain HTTP/1.0 GET and checks for a 200 status line.
+fn http_ok(url: &str) -> bool {
+    let rest = url.strip_prefix("http://").unwrap_or(url);
+    let (host, path) = match rest.split_once('/') {
+        Some((host, path)) => (host, format!("/{path}")),
+        None => (rest, "/".to_string()),
+    };
+    let address = if host.contains(':') { host.to_string() } else { format!("{host}:80") };
+
+    let Some(mut stream) = connect(&address) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+
+    let request = format!("GET {path} HTTP/1.0\r\nHost: {host}\r\nConnection: close\r\n\r\n");
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status).is_ok()
+        && status.split_whitespace().nth(1) == Some("200")
+}
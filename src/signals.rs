@@ -0,0 +1,130 @@
+use crate::{logger::Logger, process};
+use std::{
+    cell::Cell,
+    process::exit,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+#[cfg(unix)]
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+#[cfg(windows)]
+const SIGINT: i32 = 2;
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static CLEANUP_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Parks the calling thread for good once a shutdown is in progress, so no new commands are
+/// started and no failures are reported while children are being stopped. The cleanup thread, and
+/// the threads it spawns, are exempt.
+pub fn block_if_shutting_down() {
+    if is_shutting_down() && !CLEANUP_THREAD.get() {
+        loop {
+            thread::park();
+        }
+    }
+}
+
+/// Whether the calling thread runs the cleanup tasks. Threads spawned to run commands pass this on
+/// with `set_cleanup_thread`, so the commands of a concurrent cleanup task are exempt as well.
+pub fn is_cleanup_thread() -> bool {
+    CLEANUP_THREAD.get()
+}
+
+pub fn set_cleanup_thread(cleanup: bool) {
+    CLEANUP_THREAD.set(cleanup);
+}
+
+type Cleanup = Box<dyn FnOnce() + Send>;
+
+static TIMEOUT: Mutex<Duration> = Mutex::new(Duration::ZERO);
+static CLEANUP: Mutex<Option<Cleanup>> = Mutex::new(None);
+
+/// Installs the SIGINT/SIGTERM handler. The first signal is forwarded to every running child
+/// process group, then `cleanup` runs and the process exits with 130. A second signal kills the
+/// children right away.
+pub fn install<F: FnOnce() + Send + 'static>(timeout: Duration, cleanup: F) {
+    *TIMEOUT.lock().unwrap() = timeout;
+    *CLEANUP.lock().unwrap() = Some(Box::new(cleanup));
+
+    #[cfg(unix)]
+    {
+        let mut signals =
+            Signals::new([SIGINT, SIGTERM]).expect("failed to register signal handler");
+
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                on_signal(signal);
+            }
+        });
+    }
+
+    #[cfg(windows)]
+    ctrlc::set_handler(|| on_signal(SIGINT)).expect("failed to register signal handler");
+}
+
+/// How long children get to exit after being signalled, from `shutdown_timeout`.
+pub fn shutdown_timeout() -> Duration {
+    *TIMEOUT.lock().unwrap()
+}
+
+/// Ends the session the same way an interrupt does, but exits with 0.
+pub fn quit() {
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    Logger::quitting(process::running().len());
+    shutdown(SIGINT, 0);
+}
+
+fn on_signal(signal: i32) {
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        Logger::warn("forcing shutdown");
+        process::kill_all();
+        exit(130);
+    }
+
+    Logger::interrupted(signal_name(signal), process::running().len());
+    shutdown(signal, 130);
+}
+
+fn shutdown(signal: i32, code: i32) {
+    let timeout = *TIMEOUT.lock().unwrap();
+    let cleanup = CLEANUP.lock().unwrap().take();
+
+    thread::spawn(move || {
+        process::terminate_all(signal, timeout);
+
+        set_cleanup_thread(true);
+        if let Some(cleanup) = cleanup {
+            cleanup();
+        }
+
+        exit(code);
+    });
+}
+
+fn signal_name(signal: i32) -> &'static str {
+    match signal {
+        SIGINT => "SIGINT",
+        #[cfg(unix)]
+        SIGTERM => "SIGTERM",
+        _ => "signal",
+    }
+}
@@ -1,7 +1,15 @@
-use crate::format::{CommandSpec, Task, TasksFile};
+use crate::format::{CommandSpec, ReadyProbe, Task, TasksFile};
+use glob::Pattern;
+use regex::Regex;
+use serde::{
+    Deserialize, Deserializer,
+    de::{DeserializeOwned, IgnoredAny, MapAccess, Visitor},
+};
 use std::{
     collections::{HashMap, HashSet},
+    fmt,
     fs::read_to_string,
+    iter,
     path::{Path, PathBuf},
     process::exit,
 };
@@ -20,48 +28,177 @@ pub fn detect_tasks_file() -> Result<PathBuf, String> {
     Err("no compatible file was found (tasks.toml, tasks.yaml, tasks.yml, tasks.json).".to_string())
 }
 
+/// Task names and aliases close to `name` by edit distance, closest first, for "did you mean"
+/// hints.
+pub fn suggest_tasks(name: &str, tasks: &HashMap<String, Task>) -> Vec<String> {
+    let max_distance = (name.chars().count() / 3).max(2);
+
+    let mut matches: Vec<(usize, &String)> = tasks
+        .iter()
+        .flat_map(|(task_name, task)| iter::once(task_name).chain(&task.aliases))
+        .map(|task| (strsim::levenshtein(name, task), task))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    matches.sort();
+    matches.dedup_by(|a, b| a.1 == b.1);
+
+    matches.into_iter().take(3).map(|(_, task)| task.clone()).collect()
+}
+
+/// The task called `name`, or that has `name` as one of its aliases.
+pub fn resolve_task(name: &str, tasks: &HashMap<String, Task>) -> Option<String> {
+    if tasks.contains_key(name) {
+        return Some(name.to_string());
+    }
+
+    tasks.iter().find(|(_, task)| task.aliases.iter().any(|a| a == name)).map(|(n, _)| n.clone())
+}
+
+/// Task names matching a glob such as `test:*`, sorted.
+pub fn matching_tasks(pattern: &str, tasks: &HashMap<String, Task>) -> Vec<String> {
+    let Ok(pattern) = Pattern::new(pattern) else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> =
+        tasks.keys().filter(|name| pattern.matches(name)).cloned().collect();
+    names.sort();
+    names
+}
+
+/// Task names carrying any of `tags`, sorted.
+pub fn tagged_tasks(tags: &[String], tasks: &HashMap<String, Task>) -> Vec<String> {
+    let mut names: Vec<String> = tasks
+        .iter()
+        .filter(|(_, task)| task.tags.iter().any(|tag| tags.contains(tag)))
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    names
+}
+
 pub fn parse_tasks_file(path: PathBuf) -> TasksFile {
-    let content = read_to_string(&path).unwrap();
+    load_tasks_file(&path).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        exit(1);
+    })
+}
+
+/// Reads and parses a tasks file, returning the error message instead of exiting.
+pub fn load_tasks_file(path: &Path) -> Result<TasksFile, String> {
+    let content = read_to_string(path).map_err(|err| format!("error reading tasks file: {err}"))?;
+
+    let mut file: TasksFile = parse(path, &content)?;
+    let declared: DeclaredOrder = parse(path, &content)?;
+    file.order = declared.tasks;
+    resolve_aliases(&mut file);
+    Ok(file)
+}
+
+/// Replaces the aliases used as `deps`, `cleanup` or `default` by the names of their tasks, so the
+/// rest only ever deals with task names.
+fn resolve_aliases(file: &mut TasksFile) {
+    let tasks = file.tasks.clone();
+    let resolved = |name: &String| resolve_task(name, &tasks).unwrap_or_else(|| name.clone());
 
+    for task in file.tasks.values_mut() {
+        for dep in &mut task.deps {
+            *dep = resolved(dep);
+        }
+    }
+    for name in &mut file.config.cleanup {
+        *name = resolved(name);
+    }
+    if let Some(default) = &mut file.config.default {
+        *default = resolved(default);
+    }
+}
+
+fn parse<T: DeserializeOwned>(path: &Path, content: &str) -> Result<T, String> {
     match path.extension().and_then(|s| s.to_str()) {
         Some("toml") => {
-            let parsed: TasksFile = toml::from_str(&content).unwrap_or_else(|err| {
-                eprintln!("error parsing toml file: {err}");
-                exit(1);
-            });
-
-            parsed
+            toml::from_str(content).map_err(|err| format!("error parsing toml file: {err}"))
         }
         Some("yaml") | Some("yml") => {
-            let parsed: TasksFile = serde_yaml::from_str(&content).unwrap_or_else(|err| {
-                eprintln!("error parsing yaml file: {err}");
-                exit(1);
-            });
-
-            parsed
+            serde_yaml::from_str(content).map_err(|err| format!("error parsing yaml file: {err}"))
         }
         Some("json") => {
-            let parsed: TasksFile = serde_json::from_str(&content).unwrap_or_else(|err| {
-                eprintln!("error parsing json file: {err}");
-                exit(1);
-            });
+            serde_json::from_str(content).map_err(|err| format!("error parsing json file: {err}"))
+        }
+        _ => Err("unsupported file format.".to_string()),
+    }
+}
+
+/// The task names of a tasks file, in the order they are declared.
+#[derive(Deserialize)]
+struct DeclaredOrder {
+    #[serde(default, deserialize_with = "map_keys")]
+    tasks: Vec<String>,
+}
+
+fn map_keys<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    struct Keys;
+
+    impl<'de> Visitor<'de> for Keys {
+        type Value = Vec<String>;
 
-            parsed
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a map of tasks")
         }
-        _ => {
-            eprintln!("unsupported file format.");
-            exit(1);
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut keys = Vec::new();
+            while let Some((key, IgnoredAny)) = map.next_entry::<String, IgnoredAny>()? {
+                keys.push(key);
+            }
+            Ok(keys)
         }
     }
+
+    deserializer.deserialize_map(Keys)
 }
 
 #[derive(Debug)]
 pub enum ValidationError {
     DuplicatedTask(String),
+    DuplicatedAlias { task: String, alias: String },
     DependencyNotFound { task: String, dep: String },
     EmptyCommand(String),
     SelfDependency(String),
     CyclicDependency { cycle: Vec<String> },
+    CleanupTaskNotFound(String),
+    InvalidWatchPattern { task: String, pattern: String },
+    InvalidReadyPattern { task: String, pattern: String },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::DuplicatedTask(name) => write!(f, "duplicated task name: {name}"),
+            ValidationError::DuplicatedAlias { task, alias } => {
+                write!(f, "task '{task}' has an alias that is already taken: '{alias}'")
+            }
+            ValidationError::DependencyNotFound { task, dep } => {
+                write!(f, "task '{task}' has a missing dependency: '{dep}'")
+            }
+            ValidationError::EmptyCommand(name) => write!(f, "task '{name}' has an empty command"),
+            ValidationError::SelfDependency(name) => {
+                write!(f, "task '{name}' has a self-dependency")
+            }
+            ValidationError::CyclicDependency { cycle } => {
+                write!(f, "cyclic dependency detected: {}", cycle.join(" → "))
+            }
+            ValidationError::CleanupTaskNotFound(name) => {
+                write!(f, "cleanup task '{name}' does not exist")
+            }
+            ValidationError::InvalidWatchPattern { task, pattern } => {
+                write!(f, "task '{task}' has an invalid watch pattern: '{pattern}'")
+            }
+            ValidationError::InvalidReadyPattern { task, pattern } => {
+                write!(f, "task '{task}' has an invalid ready log pattern: '{pattern}'")
+            }
+        }
+    }
 }
 
 pub fn validate_tasks_file(file: TasksFile) -> Vec<ValidationError> {
@@ -75,6 +212,23 @@ pub fn validate_tasks_file(file: TasksFile) -> Vec<ValidationError> {
     }
 
     let task_names: HashSet<_> = file.tasks.keys().map(|name| name.as_str()).collect();
+
+    // by name rather than `file.order`, which repeats the keys a file declares twice
+    let mut names: Vec<&String> = file.tasks.keys().collect();
+    names.sort();
+
+    let mut aliases = HashSet::new();
+    for name in names {
+        for alias in &file.tasks[name].aliases {
+            if task_names.contains(alias.as_str()) || !aliases.insert(alias) {
+                errors.push(ValidationError::DuplicatedAlias {
+                    task: name.clone(),
+                    alias: alias.clone(),
+                });
+            }
+        }
+    }
+
     for (name, task) in &file.tasks {
         for dep in &task.deps {
             if !task_names.contains(dep.as_str()) {
@@ -105,6 +259,35 @@ pub fn validate_tasks_file(file: TasksFile) -> Vec<ValidationError> {
         }
     }
 
+    for (name, task) in &file.tasks {
+        for pattern in task.watch.iter().chain(&task.watch_ignore).chain(&task.sources) {
+            let glob = pattern.strip_prefix('!').unwrap_or(pattern);
+            if Pattern::new(glob).is_err() {
+                errors.push(ValidationError::InvalidWatchPattern {
+                    task: name.clone(),
+                    pattern: pattern.clone(),
+                });
+            }
+        }
+    }
+
+    for (name, task) in &file.tasks {
+        if let Some(ReadyProbe::Log(pattern)) = &task.ready
+            && Regex::new(pattern).is_err()
+        {
+            errors.push(ValidationError::InvalidReadyPattern {
+                task: name.clone(),
+                pattern: pattern.clone(),
+            });
+        }
+    }
+
+    for name in &file.config.cleanup {
+        if !task_names.contains(name.as_str()) {
+            errors.push(ValidationError::CleanupTaskNotFound(name.clone()));
+        }
+    }
+
     let cycles = detect_cycles(&file.tasks);
     for cycle in cycles {
         errors.push(ValidationError::CyclicDependency { cycle });
@@ -113,7 +296,7 @@ pub fn validate_tasks_file(file: TasksFile) -> Vec<ValidationError> {
     errors
 }
 
-fn detect_cycles(tasks: &HashMap<String, Task>) -> Vec<Vec<String>> {
+pub fn detect_cycles(tasks: &HashMap<String, Task>) -> Vec<Vec<String>> {
     let mut cycles = Vec::new();
     let mut visiting = HashSet::new();
     let mut visited = HashSet::new();
@@ -1,109 +1,709 @@
-use crate::logger::Logger;
+use crate::{
+    format::{Config, Task, WatchMode},
+    logger::Logger,
+};
 use glob::Pattern;
-use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
-use std::collections::HashSet;
+use ignore::{
+    WalkBuilder,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+use notify::{EventHandler, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::path::Path;
-use std::sync::mpsc::channel;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
 use std::time::{Duration, Instant};
 
-pub fn watch_task<F: Fn() + Send + Sync + 'static>(
-    patterns: &[String],
-    debounce_ms: u64,
-    callback: F,
-) {
-    let (tx, rx) = channel();
-
-    let mut watcher: RecommendedWatcher =
-        Watcher::new(tx, Config::default().with_poll_interval(Duration::from_millis(100)))
-            .expect("failed to create file watcher");
-
-    let compiled_patterns: Vec<Pattern> =
-        patterns.iter().map(|p| Pattern::new(p).expect("invalid glob pattern")).collect();
-
-    let mut watched_dirs = HashSet::new();
-
-    for pattern in patterns {
-        let path = Path::new(pattern);
-
-        if let Some(parent) = path.parent() {
-            let dir_to_watch = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
-            watched_dirs.insert(dir_to_watch.to_path_buf());
-        } else {
-            watched_dirs.insert(Path::new(".").to_path_buf());
-        }
-    }
-
-    for dir in &watched_dirs {
-        Logger::watching_dir(&dir.display().to_string());
-        watcher
-            .watch(dir, RecursiveMode::Recursive)
-            .unwrap_or_else(|_| panic!("failed to watch path: {}", dir.display()));
-    }
-
-    Logger::watching_patterns(patterns);
-
-    let mut last_event_time = Instant::now();
-    let debounce_duration = Duration::from_millis(debounce_ms);
-
-    loop {
-        match rx.recv() {
-            Ok(event_result) => match event_result {
-                Ok(event) => {
-                    let mut should_trigger = false;
-
-                    for path in &event.paths {
-                        let is_relevant_event = matches!(
-                            &event.kind,
-                            notify::EventKind::Modify(_)
-                                | notify::EventKind::Create(_)
-                                | notify::EventKind::Remove(_)
-                        );
-
-                        if !is_relevant_event {
-                            continue;
-                        }
-
-                        let relative_path = if path.is_absolute() {
-                            if let Ok(current_dir) = env::current_dir() {
-                                path.strip_prefix(current_dir).unwrap_or(path).to_path_buf()
-                            } else {
-                                path.to_path_buf()
-                            }
-                        } else {
-                            path.to_path_buf()
-                        };
-
-                        for pattern in &compiled_patterns {
-                            if pattern.matches_path(&relative_path) {
-                                Logger::file_change(
-                                    &relative_path.display().to_string(),
-                                    pattern.as_str(),
-                                );
-                                should_trigger = true;
-                                break;
-                            }
-                        }
-
-                        if should_trigger {
-                            break;
-                        }
+pub struct WatchOptions {
+    pub patterns: Vec<String>,
+    pub ignore: Vec<String>,
+    pub gitignore: bool,
+    pub debounce_ms: u64,
+    pub mode: WatchMode,
+    pub hash: bool,
+}
+
+impl WatchOptions {
+    /// Splits the task's `watch` list into include patterns and `!pattern` negations, which are
+    /// merged into `watch_ignore`.
+    pub fn from_task(task: &Task, config: &Config) -> Self {
+        let mut patterns = Vec::new();
+        let mut ignore = task.watch_ignore.clone();
+
+        for pattern in &task.watch {
+            match pattern.strip_prefix('!') {
+                Some(negated) => ignore.push(negated.to_string()),
+                None => patterns.push(pattern.clone()),
+            }
+        }
+
+        Self {
+            patterns,
+            ignore,
+            gitignore: task.watch_gitignore,
+            debounce_ms: task.watch_debounce,
+            mode: task.watch_mode.unwrap_or(config.watch_mode),
+            hash: task.watch_hash,
+        }
+    }
+}
+
+struct Subscription {
+    task_name: String,
+    patterns: Vec<Pattern>,
+    raw_patterns: Vec<String>,
+    roots: Vec<PathBuf>,
+    recursive: bool,
+    rules: IgnoreRules,
+    mode: WatchMode,
+    debounce: Duration,
+    changed: Vec<PathBuf>,
+    deadline: Option<Instant>,
+    /// Content hashes of the matched files as of the last run, with `watch_hash`.
+    hashes: Option<HashMap<PathBuf, Option<u64>>>,
+}
+
+impl Subscription {
+    fn new(task_name: &str, options: WatchOptions) -> Self {
+        let patterns = options
+            .patterns
+            .iter()
+            .map(|p| Pattern::new(p).expect("invalid glob pattern"))
+            .collect();
+
+        Self {
+            task_name: task_name.to_string(),
+            patterns,
+            roots: watch_roots(&options.patterns),
+            recursive: true,
+            rules: IgnoreRules::new(&options),
+            mode: options.mode,
+            debounce: Duration::from_millis(options.debounce_ms),
+            raw_patterns: options.patterns,
+            changed: Vec::new(),
+            deadline: None,
+            hashes: options.hash.then(HashMap::new),
+        }
+    }
+
+    fn take_due(&mut self, now: Instant) -> Option<(String, Change)> {
+        if self.deadline.is_none_or(|at| at > now) {
+            return None;
+        }
+
+        self.deadline = None;
+        let mut paths = std::mem::take(&mut self.changed);
+        let mut fresh = ContentHashes::new();
+
+        // the baseline only moves once a run saw the new content, see `WatchHandle::commit`
+        if let Some(hashes) = &self.hashes {
+            paths.retain(|path| {
+                let hash = content_hash(path);
+                let changed = hashes.get(path) != Some(&hash);
+                if changed {
+                    fresh.insert(path.clone(), hash);
+                }
+                changed
+            });
+
+            if paths.is_empty() {
+                Logger::content_unchanged(&self.task_name);
+                return None;
+            }
+        }
+
+        Some((self.task_name.clone(), Change { paths, hashes: fresh }))
+    }
+}
+
+/// Content hashes of files, `None` for files that are gone.
+pub type ContentHashes = HashMap<PathBuf, Option<u64>>;
+
+/// The changed files of a watched task.
+#[derive(Debug, Clone, Default)]
+pub struct Change {
+    pub paths: Vec<PathBuf>,
+    /// Their new content hashes, with `watch_hash`. They become the baseline once handed back
+    /// through [`WatchHandle::commit`].
+    pub hashes: ContentHashes,
+}
+
+enum Message {
+    Event(notify::Result<notify::Event>),
+    Subscribe(String, WatchOptions),
+    Unsubscribe(String),
+    Pause(bool),
+    Commit(String, ContentHashes),
+}
+
+pub enum WatchEvent {
+    /// Watched tasks whose files changed, with the changed paths.
+    Changed(Vec<(String, Change)>),
+    /// The tasks file itself changed.
+    TasksFileChanged,
+}
+
+/// Changes the subscriptions of a running [`WatchService`].
+#[derive(Clone)]
+pub struct WatchHandle {
+    tx: Sender<Message>,
+}
+
+impl WatchHandle {
+    pub fn subscribe(&self, task_name: &str, options: WatchOptions) {
+        let _ = self.tx.send(Message::Subscribe(task_name.to_string(), options));
+    }
+
+    pub fn unsubscribe(&self, task_name: &str) {
+        let _ = self.tx.send(Message::Unsubscribe(task_name.to_string()));
+    }
+
+    /// Makes the hashes of a change the baseline of the task, once a run saw that content. Until
+    /// then, the same content still counts as changed.
+    pub fn commit(&self, task_name: &str, hashes: ContentHashes) {
+        if !hashes.is_empty() {
+            let _ = self.tx.send(Message::Commit(task_name.to_string(), hashes));
+        }
+    }
+
+    /// While paused, changes are dropped rather than queued.
+    pub fn set_paused(&self, paused: bool) {
+        let _ = self.tx.send(Message::Pause(paused));
+    }
+}
+
+/// A single file watcher shared by every watched task. Each directory is registered once, and
+/// every event is routed to all the tasks whose patterns match it.
+pub struct WatchService {
+    subscriptions: Vec<Subscription>,
+    tasks_file: Option<Subscription>,
+    poll_interval: Duration,
+    tx: Sender<Message>,
+    rx: Receiver<Message>,
+    logged_roots: HashSet<PathBuf>,
+    paused: bool,
+}
+
+impl WatchService {
+    pub fn new(poll_interval: Duration) -> Self {
+        let (tx, rx) = channel();
+        Self {
+            subscriptions: Vec::new(),
+            tasks_file: None,
+            poll_interval,
+            tx,
+            rx,
+            logged_roots: HashSet::new(),
+            paused: false,
+        }
+    }
+
+    pub fn handle(&self) -> WatchHandle {
+        WatchHandle { tx: self.tx.clone() }
+    }
+
+    pub fn subscribe(&mut self, task_name: &str, options: WatchOptions) {
+        self.subscriptions.push(Subscription::new(task_name, options));
+    }
+
+    /// Also watches the tasks file, reporting its changes as [`WatchEvent::TasksFileChanged`].
+    pub fn watch_tasks_file(&mut self, path: &Path, mode: WatchMode) {
+        let path = path.canonicalize().unwrap_or(path.to_path_buf());
+        let parent = path.parent().map(Path::to_path_buf).unwrap_or(PathBuf::from("."));
+
+        let options = WatchOptions {
+            patterns: vec![Pattern::escape(&relative(&path).to_string_lossy())],
+            ignore: Vec::new(),
+            gitignore: false,
+            debounce_ms: 100,
+            mode,
+            hash: false,
+        };
+
+        let mut subscription = Subscription::new("", options);
+        subscription.roots = vec![parent];
+        subscription.recursive = false;
+        self.tasks_file = Some(subscription);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    /// Runs the event loop. Each task is debounced on its own; once one or more tasks have been
+    /// quiet for their `watch_debounce`, `callback` receives them together with their changed
+    /// paths, so the caller can coordinate the re-runs.
+    pub fn run<F: Fn(WatchEvent)>(mut self, callback: F) {
+        let tx = self.tx.clone();
+        let handler = move |event| {
+            let _ = tx.send(Message::Event(event));
+        };
+        let mut backends = Backends::new(handler, self.poll_interval);
+
+        let mut subscriptions = std::mem::take(&mut self.subscriptions);
+        for subscription in &mut subscriptions {
+            self.register(subscription, &mut backends);
+        }
+        self.subscriptions = subscriptions;
+
+        if let Some(mut tasks_file) = self.tasks_file.take() {
+            self.register(&mut tasks_file, &mut backends);
+            self.tasks_file = Some(tasks_file);
+        }
+
+        loop {
+            // trailing-edge debounce: a task fires once its changes have been quiet for
+            // `watch_debounce`
+            let deadline =
+                self.subscriptions.iter().chain(&self.tasks_file).filter_map(|s| s.deadline).min();
+            let received = match deadline {
+                Some(at) => self.rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+                None => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match received {
+                Ok(Message::Event(Ok(_))) if self.paused => {}
+                Ok(Message::Event(Ok(event))) => self.handle_event(&event, &mut backends),
+                Ok(Message::Event(Err(e))) => Logger::error(&format!("watch error: {e:?}")),
+                Ok(Message::Subscribe(task_name, options)) => {
+                    let mut subscription = Subscription::new(&task_name, options);
+                    self.register(&mut subscription, &mut backends);
+                    self.subscriptions.push(subscription);
+                }
+                Ok(Message::Unsubscribe(task_name)) => {
+                    self.subscriptions.retain(|s| s.task_name != task_name);
+                }
+                Ok(Message::Pause(paused)) => {
+                    self.paused = paused;
+                    for subscription in self.subscriptions.iter_mut().chain(&mut self.tasks_file) {
+                        subscription.changed.clear();
+                        subscription.deadline = None;
+                    }
+                }
+                Ok(Message::Commit(task_name, committed)) => {
+                    if let Some(hashes) = self
+                        .subscriptions
+                        .iter_mut()
+                        .find(|s| s.task_name == task_name)
+                        .and_then(|s| s.hashes.as_mut())
+                    {
+                        hashes.extend(committed);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let now = Instant::now();
+
+                    if self.tasks_file.as_mut().and_then(|s| s.take_due(now)).is_some() {
+                        callback(WatchEvent::TasksFileChanged);
+                    }
+
+                    let due: Vec<(String, Change)> =
+                        self.subscriptions.iter_mut().filter_map(|s| s.take_due(now)).collect();
+                    if !due.is_empty() {
+                        callback(WatchEvent::Changed(due));
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    Logger::error("channel error: watcher disconnected");
+                    break;
+                }
+            }
+        }
+    }
+
+    fn register<H: EventHandler + Clone>(
+        &mut self,
+        subscription: &mut Subscription,
+        backends: &mut Backends<H>,
+    ) {
+        for root in &subscription.roots {
+            if !root.exists() {
+                Logger::error(&format!("watch root does not exist: {}", root.display()));
+                continue;
+            }
+
+            // the tasks file only needs its own directory
+            if !subscription.recursive {
+                backends.watch(root, subscription.mode);
+                continue;
+            }
+
+            if self.logged_roots.insert(root.clone()) {
+                Logger::watching_dir(&root.display().to_string());
+            }
+            let files = register_dirs(backends, root, subscription.mode, &mut subscription.rules);
+
+            // the baseline that later changes are compared against
+            if let Some(mut hashes) = subscription.hashes.take() {
+                for file in files.iter().map(|file| relative(file)) {
+                    let matched = subscription.patterns.iter().any(|p| p.matches_path(&file));
+                    if matched && !subscription.rules.is_ignored(&file, false) {
+                        let hash = content_hash(&file);
+                        hashes.insert(file, hash);
+                    }
+                }
+                subscription.hashes = Some(hashes);
+            }
+        }
+
+        if subscription.recursive {
+            Logger::watching_patterns(&subscription.raw_patterns);
+        }
+    }
+
+    fn handle_event<H: EventHandler + Clone>(
+        &mut self,
+        event: &notify::Event,
+        backends: &mut Backends<H>,
+    ) {
+        let is_relevant_event = matches!(
+            &event.kind,
+            notify::EventKind::Modify(_)
+                | notify::EventKind::Create(_)
+                | notify::EventKind::Remove(_)
+        );
+
+        if !is_relevant_event {
+            return;
+        }
+
+        for path in &event.paths {
+            let relative_path = relative(path);
+            let is_dir = path.is_dir();
+
+            for subscription in self.subscriptions.iter_mut().chain(&mut self.tasks_file) {
+                if subscription.rules.is_ignored(&relative_path, is_dir) {
+                    continue;
+                }
+
+                // directories created after startup are not covered by the watcher yet
+                if is_dir
+                    && subscription.recursive
+                    && matches!(event.kind, notify::EventKind::Create(_))
+                    && subscription
+                        .roots
+                        .iter()
+                        .any(|root| relative_path.starts_with(relative(root)))
+                {
+                    register_dirs(backends, path, subscription.mode, &mut subscription.rules);
+                }
+
+                let matched = subscription.patterns.iter().find(|p| p.matches_path(&relative_path));
+
+                if let Some(pattern) = matched {
+                    if !subscription.changed.contains(&relative_path) {
+                        Logger::file_change(&relative_path.display().to_string(), pattern.as_str());
+                        subscription.changed.push(relative_path.clone());
                     }
+                    subscription.deadline = Some(Instant::now() + subscription.debounce);
+                }
+            }
+        }
+    }
+}
 
-                    if should_trigger {
-                        let now = Instant::now();
-                        if now.duration_since(last_event_time) >= debounce_duration {
-                            last_event_time = now;
-                            callback();
-                        }
+/// The native and the polling watcher, created on first use and feeding the same channel.
+struct Backends<H> {
+    handler: H,
+    poll_interval: Duration,
+    native: Option<RecommendedWatcher>,
+    poll: Option<PollWatcher>,
+    fell_back: bool,
+    registered: HashSet<(PathBuf, WatchMode)>,
+}
+
+impl<H: EventHandler + Clone> Backends<H> {
+    fn new(handler: H, poll_interval: Duration) -> Self {
+        Self {
+            handler,
+            poll_interval,
+            native: None,
+            poll: None,
+            fell_back: false,
+            registered: HashSet::new(),
+        }
+    }
+
+    /// Registers `dir` with the backend for `mode`. When native watching fails (inotify limits,
+    /// unsupported filesystems...), the directory is polled instead.
+    fn watch(&mut self, dir: &Path, mode: WatchMode) {
+        let key = dir.canonicalize().unwrap_or(dir.to_path_buf());
+        if self.registered.contains(&(key.clone(), mode)) {
+            return;
+        }
+
+        if mode == WatchMode::Native {
+            match self.watch_native(dir) {
+                Ok(()) => {
+                    self.registered.insert((key, mode));
+                    return;
+                }
+                Err(e) => {
+                    if !self.fell_back {
+                        self.fell_back = true;
+                        Logger::warn(&format!(
+                            "native file watching failed ({e}), polling instead"
+                        ));
                     }
+                    self.registered.insert((key.clone(), mode));
                 }
-                Err(e) => Logger::error(&format!("watch error: {e:?}")),
-            },
-            Err(e) => {
-                Logger::error(&format!("channel error: {e:?}"));
-                break;
             }
         }
+
+        if !self.registered.insert((key, WatchMode::Poll)) {
+            return;
+        }
+
+        if let Err(e) = self.watch_poll(dir) {
+            Logger::error(&format!("failed to watch {}: {e}", dir.display()));
+        }
+    }
+
+    fn watch_native(&mut self, dir: &Path) -> notify::Result<()> {
+        if self.native.is_none() {
+            let handler = self.handler.clone();
+            self.native = Some(RecommendedWatcher::new(handler, notify::Config::default())?);
+        }
+
+        self.native.as_mut().unwrap().watch(dir, RecursiveMode::NonRecursive)
+    }
+
+    fn watch_poll(&mut self, dir: &Path) -> notify::Result<()> {
+        if self.poll.is_none() {
+            let config = notify::Config::default().with_poll_interval(self.poll_interval);
+            self.poll = Some(PollWatcher::new(self.handler.clone(), config)?);
+        }
+
+        self.poll.as_mut().unwrap().watch(dir, RecursiveMode::NonRecursive)
+    }
+}
+
+/// Registers `root` and every directory below it that isn't ignored, one by one, so ignored
+/// trees such as `target/` or `node_modules/` never use up watch handles.
+fn register_dirs<H: EventHandler + Clone>(
+    backends: &mut Backends<H>,
+    root: &Path,
+    mode: WatchMode,
+    rules: &mut IgnoreRules,
+) -> Vec<PathBuf> {
+    let mut walker = WalkBuilder::new(root);
+    walker.standard_filters(rules.gitignore).hidden(false).require_git(false);
+
+    let patterns = rules.patterns.clone();
+    let gitignore = rules.gitignore;
+    walker.filter_entry(move |entry| {
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        !is_dir || !dir_ignored(&patterns, gitignore, &relative(entry.path()))
+    });
+
+    let mut files = Vec::new();
+    for entry in walker.build().flatten() {
+        if !entry.file_type().is_some_and(|t| t.is_dir()) {
+            files.push(entry.into_path());
+            continue;
+        }
+
+        let dir = entry.path();
+        if rules.gitignore {
+            rules.add_gitignores(dir);
+        }
+
+        backends.watch(dir, mode);
+    }
+
+    files
+}
+
+/// Hashes the contents of a file, or `None` when it can't be read, e.g. because it was removed.
+fn content_hash(path: &Path) -> Option<u64> {
+    let bytes = fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Paths are matched relative to the working directory.
+pub fn relative(path: &Path) -> PathBuf {
+    let path = match env::current_dir() {
+        Ok(current_dir) if path.is_absolute() => path.strip_prefix(current_dir).unwrap_or(path),
+        _ => path,
+    };
+
+    path.components().filter(|c| *c != Component::CurDir).collect()
+}
+
+fn dir_ignored(patterns: &[Pattern], gitignore: bool, dir: &Path) -> bool {
+    if gitignore && dir.file_name().is_some_and(|name| name == ".git") {
+        return true;
+    }
+
+    patterns.iter().any(|pattern| {
+        pattern.matches_path(dir)
+            || pattern
+                .as_str()
+                .strip_suffix("/**")
+                .and_then(|prefix| Pattern::new(prefix).ok())
+                .is_some_and(|prefix| prefix.matches_path(dir))
+    })
+}
+
+struct IgnoreRules {
+    patterns: Vec<Pattern>,
+    gitignore: bool,
+    gitignores: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+    fn new(options: &WatchOptions) -> Self {
+        let mut rules = Self {
+            patterns: options.ignore.iter().filter_map(|p| Pattern::new(p).ok()).collect(),
+            gitignore: options.gitignore,
+            gitignores: Vec::new(),
+        };
+
+        if rules.gitignore {
+            rules.add_gitignores(Path::new("."));
+        }
+
+        rules
+    }
+
+    /// Loads the `.gitignore` and `.ignore` files of `dir`, if any.
+    fn add_gitignores(&mut self, dir: &Path) {
+        let Ok(dir) = dir.canonicalize() else {
+            return;
+        };
+
+        if self.gitignores.iter().any(|gitignore| gitignore.path() == dir) {
+            return;
+        }
+
+        let files: Vec<PathBuf> =
+            [".gitignore", ".ignore"].iter().map(|f| dir.join(f)).filter(|f| f.is_file()).collect();
+        if files.is_empty() {
+            return;
+        }
+
+        let mut builder = GitignoreBuilder::new(&dir);
+        for file in files {
+            builder.add(file);
+        }
+        if let Ok(gitignore) = builder.build() {
+            self.gitignores.push(gitignore);
+        }
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ancestors = path.ancestors().skip(1).filter(|a| !a.as_os_str().is_empty());
+        if ancestors.any(|dir| dir_ignored(&self.patterns, self.gitignore, dir))
+            || (is_dir && dir_ignored(&self.patterns, self.gitignore, path))
+            || self.patterns.iter().any(|p| p.matches_path(path))
+        {
+            return true;
+        }
+
+        let Ok(absolute) = env::current_dir().map(|cwd| cwd.join(path)) else {
+            return false;
+        };
+
+        self.gitignores
+            .iter()
+            .filter(|gitignore| absolute.starts_with(gitignore.path()))
+            .any(|gitignore| gitignore.matched_path_or_any_parents(&absolute, is_dir).is_ignore())
+    }
+}
+
+/// Returns the longest literal directory prefix of a glob pattern, e.g. `src` for `src/**/*.rs`
+/// and `.` for `*.toml`. A pattern without wildcards is a file, so its parent is returned.
+pub fn glob_base(pattern: &str) -> PathBuf {
+    let is_glob = |part: &str| part.contains(['*', '?', '[', '{']);
+
+    let mut base = PathBuf::new();
+    let mut literal = true;
+    for component in Path::new(pattern).components() {
+        if let Component::Normal(part) = component
+            && is_glob(&part.to_string_lossy())
+        {
+            literal = false;
+            break;
+        }
+        base.push(component);
+    }
+
+    if literal {
+        base.pop();
+    }
+
+    if base.as_os_str().is_empty() { PathBuf::from(".") } else { base }
+}
+
+/// Directories to watch recursively for `patterns`, without any root nested inside another.
+pub fn watch_roots(patterns: &[String]) -> Vec<PathBuf> {
+    // `.` is kept as an empty path while merging, so every relative root counts as nested in it
+    let mut bases: Vec<PathBuf> = patterns
+        .iter()
+        .map(|p| glob_base(p).components().filter(|c| *c != Component::CurDir).collect())
+        .collect();
+    bases.sort();
+    bases.dedup();
+
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for base in bases {
+        let nested =
+            |root: &PathBuf| base.starts_with(root) && base.is_absolute() == root.is_absolute();
+        if !roots.iter().any(nested) {
+            roots.push(base);
+        }
+    }
+
+    roots
+        .into_iter()
+        .map(|root| if root.as_os_str().is_empty() { PathBuf::from(".") } else { root })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roots(patterns: &[&str]) -> Vec<PathBuf> {
+        watch_roots(&patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn glob_base_stops_at_the_first_wildcard() {
+        assert_eq!(glob_base("src/**/*.rs"), PathBuf::from("src"));
+        assert_eq!(glob_base("src/{a,b}/*.rs"), PathBuf::from("src"));
+        assert_eq!(glob_base("*.toml"), PathBuf::from("."));
+    }
+
+    #[test]
+    fn glob_base_of_a_literal_file_is_its_parent() {
+        assert_eq!(glob_base("src/main.rs"), PathBuf::from("src"));
+        assert_eq!(glob_base("Cargo.toml"), PathBuf::from("."));
+    }
+
+    #[test]
+    fn watch_roots_merges_nested_roots() {
+        assert_eq!(roots(&["src/**", "./src/a/*"]), vec![PathBuf::from("src")]);
+        assert_eq!(roots(&["src/**/*.rs", "*.toml"]), vec![PathBuf::from(".")]);
+        assert_eq!(
+            roots(&["src/*.rs", "tests/*.rs"]),
+            vec![PathBuf::from("src"), PathBuf::from("tests")]
+        );
+    }
+
+    #[test]
+    fn watch_roots_keeps_absolute_and_relative_roots_apart() {
+        assert_eq!(
+            roots(&["*.toml", "/tmp/shared/**"]),
+            vec![PathBuf::from("."), PathBuf::from("/tmp/shared")]
+        );
+        assert_eq!(
+            roots(&["/tmp/**", "tmp/*.rs"]),
+            vec![PathBuf::from("/tmp"), PathBuf::from("tmp")]
+        );
     }
 }